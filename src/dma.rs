@@ -1,7 +1,16 @@
 //! DMA abstractions
 
+use core::cell::UnsafeCell;
 use core::mem::size_of;
 use core::ops::Not;
+use core::sync::atomic::{self, Ordering};
+use core::task::Waker;
+use cortex_m::interrupt;
+use stm32f429::{USART1, USART2, USART3, UART4, UART5, USART6, UART7, UART8,
+                SPI1, SPI2, SPI3, SPI4, SPI5, SPI6,
+                I2C1, I2C2, I2C3,
+                TIM1, TIM2, TIM3, TIM4, TIM5, TIM6, TIM7, TIM8,
+                ADC1, ADC2, ADC3, SDIO, DCMI};
 use rcc::AHB1;
 
 /// DMA channel, implemented by the types `C0`, `C1`, `C2`, …
@@ -60,12 +69,289 @@ pub trait DmaExt {
     fn split(self, ahb: &mut AHB1) -> Self::Streams;
 }
 
+/// DMA error
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// Transfer error
+    Transfer,
+    /// The DMA has overwritten data that had not been consumed yet
+    Overrun,
+    /// A length does not match the buffer or the data available
+    InvalidLength,
+    #[doc(hidden)] _Extensible,
+}
+
 /// Events to enable interrupts for.
 pub enum Event {
     /// Half transfer
     HalfTransfer,
     /// Transfer complete
     TransferComplete,
+    /// Transfer error
+    TransferError,
+}
+
+/// DMA controller whose stream interrupts can wake the tasks awaiting
+/// transfers
+pub trait DmaInterrupt {
+    /// See `on_interrupt()`
+    fn on_interrupt();
+}
+
+/// Wake the tasks awaiting transfers on streams of `DMA` that have
+/// completed or failed.
+///
+/// Call this from the interrupt handlers of all streams of `DMA` that
+/// are used with `.await`. The interrupts of those streams are
+/// disabled until the transfer is polled again, the status flags are
+/// left untouched for it. Streams that no task awaits are left alone,
+/// so their own interrupt handling keeps working.
+pub fn on_interrupt<DMA: DmaInterrupt>() {
+    DMA::on_interrupt()
+}
+
+/// Waker of the task awaiting a DMA stream
+pub(crate) struct StreamWaker {
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+// NOTE(unsafe) all access happens in critical sections
+unsafe impl Sync for StreamWaker {}
+
+impl StreamWaker {
+    pub(crate) const fn new() -> Self {
+        StreamWaker {
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Register `waker` and run `f` in the same critical section
+    pub(crate) fn register<F: FnOnce()>(&self, waker: &Waker, f: F) {
+        interrupt::free(|_| {
+            let slot = unsafe { &mut *self.waker.get() };
+            let stale = slot.as_ref()
+                .map(|old| ! old.will_wake(waker))
+                .unwrap_or(true);
+            if stale {
+                *slot = Some(waker.clone());
+            }
+            f();
+        })
+    }
+
+    /// Remove the registered waker, running `f` only if there was one
+    pub(crate) fn take<F: FnOnce()>(&self, f: F) -> Option<Waker> {
+        interrupt::free(|_| {
+            let waker = unsafe { &mut *self.waker.get() }.take();
+            if waker.is_some() {
+                f();
+            }
+            waker
+        })
+    }
+}
+
+/// Rx direction (peripheral to memory)
+pub struct DmaRx;
+/// Tx direction (memory to peripheral)
+pub struct DmaTx;
+
+/// Possible DMA configuration for an SPI device
+pub unsafe trait SpiDmaStream<SPI, CHANNEL, DIRECTION> {}
+/// Possible DMA configuration for an I2S device
+pub unsafe trait I2sDmaStream<SPI, CHANNEL, DIRECTION> {}
+/// Possible DMA configuration for the I2S full-duplex extension
+/// (`I2S2ext`, `I2S3ext`) of an SPI device
+pub unsafe trait I2sExtDmaStream<SPI, CHANNEL, DIRECTION> {}
+/// Possible DMA configuration for a U(S)ART device
+pub unsafe trait UsartDmaStream<USART, CHANNEL, DIRECTION> {}
+/// Possible DMA configuration for an I2C device
+pub unsafe trait I2cDmaStream<I2C, CHANNEL, DIRECTION> {}
+/// Possible DMA configuration for the SDIO device
+pub unsafe trait SdioDmaStream<SDIO, CHANNEL> {}
+/// Possible DMA configuration for an ADC device
+pub unsafe trait AdcDmaStream<ADC, CHANNEL> {}
+/// Possible DMA configuration for a DAC channel (`DacCh1`, `DacCh2`)
+pub unsafe trait DacDmaStream<DAC, CHANNEL> {}
+/// Possible DMA configuration for a timer DMA request (`TimUp`,
+/// `TimCh1`, …)
+pub unsafe trait TimDmaStream<TIM, CHANNEL, REQUEST> {}
+/// Possible DMA configuration for the DCMI device
+pub unsafe trait DcmiDmaStream<DCMI, CHANNEL> {}
+/// Possible DMA configuration for a SAI block (`SaiA`, `SaiB`)
+pub unsafe trait SaiDmaStream<SAI, CHANNEL> {}
+
+/// DAC channel 1
+pub struct DacCh1;
+/// DAC channel 2
+pub struct DacCh2;
+
+/// SAI1 block A
+pub struct SaiA;
+/// SAI1 block B
+pub struct SaiB;
+
+/// Timer update DMA request
+pub struct TimUp;
+/// Timer capture/compare 1 DMA request
+pub struct TimCh1;
+/// Timer capture/compare 2 DMA request
+pub struct TimCh2;
+/// Timer capture/compare 3 DMA request
+pub struct TimCh3;
+/// Timer capture/compare 4 DMA request
+pub struct TimCh4;
+/// Timer trigger DMA request
+pub struct TimTrig;
+/// Timer commutation DMA request
+pub struct TimCom;
+
+/// Generates the marker trait impls of each stream
+macro_rules! dma_map {
+    ($($dmaX:ident::$SX:ident => {
+        $($Trait:ident<$($P:ty),+>),+
+    })+) => {
+        $($(
+            unsafe impl $Trait<$($P,)+ > for $dmaX::$SX {}
+        )+)+
+    }
+}
+
+// DMA request mapping, transcribed from RM0090 tables 42 and 43 and
+// grouped by channel
+dma_map! {
+    dma1::S0 => { SpiDmaStream<SPI3, C0, DmaRx>, I2sDmaStream<SPI3, C0, DmaRx> }
+    dma1::S2 => { SpiDmaStream<SPI3, C0, DmaRx>, I2sDmaStream<SPI3, C0, DmaRx> }
+    dma1::S3 => { SpiDmaStream<SPI2, C0, DmaRx>, I2sDmaStream<SPI2, C0, DmaRx> }
+    dma1::S4 => { SpiDmaStream<SPI2, C0, DmaTx>, I2sDmaStream<SPI2, C0, DmaTx> }
+    dma1::S5 => { SpiDmaStream<SPI3, C0, DmaTx>, I2sDmaStream<SPI3, C0, DmaTx> }
+    dma1::S7 => { SpiDmaStream<SPI3, C0, DmaTx>, I2sDmaStream<SPI3, C0, DmaTx> }
+
+    dma1::S0 => { I2cDmaStream<I2C1, C1, DmaRx> }
+    dma1::S2 => { TimDmaStream<TIM7, C1, TimUp> }
+    dma1::S4 => { TimDmaStream<TIM7, C1, TimUp> }
+    dma1::S5 => { I2cDmaStream<I2C1, C1, DmaRx> }
+    dma1::S6 => { I2cDmaStream<I2C1, C1, DmaTx> }
+    dma1::S7 => { I2cDmaStream<I2C1, C1, DmaTx> }
+
+    dma1::S0 => { TimDmaStream<TIM4, C2, TimCh1> }
+    dma1::S2 => { I2sExtDmaStream<SPI3, C2, DmaRx> }
+    dma1::S3 => { TimDmaStream<TIM4, C2, TimCh2> }
+    dma1::S4 => { I2sExtDmaStream<SPI2, C2, DmaTx> }
+    dma1::S5 => { I2sExtDmaStream<SPI3, C2, DmaTx> }
+    dma1::S6 => { TimDmaStream<TIM4, C2, TimUp> }
+    dma1::S7 => { TimDmaStream<TIM4, C2, TimCh3> }
+
+    dma1::S0 => { I2sExtDmaStream<SPI3, C3, DmaRx> }
+    dma1::S1 => { TimDmaStream<TIM2, C3, TimUp>, TimDmaStream<TIM2, C3, TimCh3> }
+    dma1::S2 => { I2cDmaStream<I2C3, C3, DmaRx> }
+    dma1::S3 => { I2sExtDmaStream<SPI2, C3, DmaRx> }
+    dma1::S4 => { I2cDmaStream<I2C3, C3, DmaTx> }
+    dma1::S5 => { TimDmaStream<TIM2, C3, TimCh1> }
+    dma1::S6 => { TimDmaStream<TIM2, C3, TimCh2>, TimDmaStream<TIM2, C3, TimCh4> }
+    dma1::S7 => { TimDmaStream<TIM2, C3, TimUp>, TimDmaStream<TIM2, C3, TimCh4> }
+
+    dma1::S0 => { UsartDmaStream<UART5, C4, DmaRx> }
+    dma1::S1 => { UsartDmaStream<USART3, C4, DmaRx> }
+    dma1::S2 => { UsartDmaStream<UART4, C4, DmaRx> }
+    dma1::S3 => { UsartDmaStream<USART3, C4, DmaTx> }
+    dma1::S4 => { UsartDmaStream<UART4, C4, DmaTx> }
+    dma1::S5 => { UsartDmaStream<USART2, C4, DmaRx> }
+    dma1::S6 => { UsartDmaStream<USART2, C4, DmaTx> }
+    dma1::S7 => { UsartDmaStream<UART5, C4, DmaTx> }
+
+    dma1::S0 => { UsartDmaStream<UART8, C5, DmaTx> }
+    dma1::S1 => { UsartDmaStream<UART7, C5, DmaTx> }
+    dma1::S2 => { TimDmaStream<TIM3, C5, TimCh4>, TimDmaStream<TIM3, C5, TimUp> }
+    dma1::S3 => { UsartDmaStream<UART7, C5, DmaRx> }
+    dma1::S4 => { TimDmaStream<TIM3, C5, TimCh1>, TimDmaStream<TIM3, C5, TimTrig> }
+    dma1::S5 => { TimDmaStream<TIM3, C5, TimCh2> }
+    dma1::S6 => { UsartDmaStream<UART8, C5, DmaRx> }
+    dma1::S7 => { TimDmaStream<TIM3, C5, TimCh3> }
+
+    dma1::S0 => { TimDmaStream<TIM5, C6, TimCh3>, TimDmaStream<TIM5, C6, TimUp> }
+    dma1::S1 => { TimDmaStream<TIM5, C6, TimCh4>, TimDmaStream<TIM5, C6, TimTrig> }
+    dma1::S2 => { TimDmaStream<TIM5, C6, TimCh1> }
+    dma1::S3 => { TimDmaStream<TIM5, C6, TimCh4>, TimDmaStream<TIM5, C6, TimTrig> }
+    dma1::S4 => { TimDmaStream<TIM5, C6, TimCh2> }
+    dma1::S6 => { TimDmaStream<TIM5, C6, TimUp> }
+
+    dma1::S1 => { TimDmaStream<TIM6, C7, TimUp> }
+    dma1::S2 => { I2cDmaStream<I2C2, C7, DmaRx> }
+    dma1::S3 => { I2cDmaStream<I2C2, C7, DmaRx> }
+    dma1::S4 => { UsartDmaStream<USART3, C7, DmaTx> }
+    dma1::S5 => { DacDmaStream<DacCh1, C7> }
+    dma1::S6 => { DacDmaStream<DacCh2, C7> }
+    dma1::S7 => { I2cDmaStream<I2C2, C7, DmaTx> }
+
+    dma2::S0 => { AdcDmaStream<ADC1, C0> }
+    dma2::S1 => { SaiDmaStream<SaiA, C0> }
+    dma2::S2 => {
+        TimDmaStream<TIM8, C0, TimCh1>, TimDmaStream<TIM8, C0, TimCh2>,
+        TimDmaStream<TIM8, C0, TimCh3>
+    }
+    dma2::S3 => { SaiDmaStream<SaiA, C0> }
+    dma2::S4 => { AdcDmaStream<ADC1, C0> }
+    dma2::S5 => { SaiDmaStream<SaiB, C0> }
+    dma2::S6 => {
+        TimDmaStream<TIM1, C0, TimCh1>, TimDmaStream<TIM1, C0, TimCh2>,
+        TimDmaStream<TIM1, C0, TimCh3>
+    }
+
+    dma2::S1 => { DcmiDmaStream<DCMI, C1> }
+    dma2::S2 => { AdcDmaStream<ADC2, C1> }
+    dma2::S3 => { AdcDmaStream<ADC2, C1> }
+    dma2::S4 => { SaiDmaStream<SaiB, C1> }
+    dma2::S5 => { SpiDmaStream<SPI6, C1, DmaTx> }
+    dma2::S6 => { SpiDmaStream<SPI6, C1, DmaRx> }
+    dma2::S7 => { DcmiDmaStream<DCMI, C1> }
+
+    dma2::S0 => { AdcDmaStream<ADC3, C2> }
+    dma2::S1 => { AdcDmaStream<ADC3, C2> }
+    dma2::S3 => { SpiDmaStream<SPI5, C2, DmaRx> }
+    dma2::S4 => { SpiDmaStream<SPI5, C2, DmaTx> }
+
+    dma2::S0 => { SpiDmaStream<SPI1, C3, DmaRx>, I2sDmaStream<SPI1, C3, DmaRx> }
+    dma2::S2 => { SpiDmaStream<SPI1, C3, DmaRx>, I2sDmaStream<SPI1, C3, DmaRx> }
+    dma2::S3 => { SpiDmaStream<SPI1, C3, DmaTx>, I2sDmaStream<SPI1, C3, DmaTx> }
+    dma2::S5 => { SpiDmaStream<SPI1, C3, DmaTx>, I2sDmaStream<SPI1, C3, DmaTx> }
+
+    dma2::S0 => { SpiDmaStream<SPI4, C4, DmaRx> }
+    dma2::S1 => { SpiDmaStream<SPI4, C4, DmaTx> }
+    dma2::S2 => { UsartDmaStream<USART1, C4, DmaRx> }
+    dma2::S3 => { SdioDmaStream<SDIO, C4> }
+    dma2::S5 => { UsartDmaStream<USART1, C4, DmaRx> }
+    dma2::S6 => { SdioDmaStream<SDIO, C4> }
+    dma2::S7 => { UsartDmaStream<USART1, C4, DmaTx> }
+
+    dma2::S1 => { UsartDmaStream<USART6, C5, DmaRx> }
+    dma2::S2 => { UsartDmaStream<USART6, C5, DmaRx> }
+    dma2::S3 => { SpiDmaStream<SPI4, C5, DmaRx> }
+    dma2::S4 => { SpiDmaStream<SPI4, C5, DmaTx> }
+    dma2::S6 => { UsartDmaStream<USART6, C5, DmaTx> }
+    dma2::S7 => { UsartDmaStream<USART6, C5, DmaTx> }
+
+    dma2::S0 => { TimDmaStream<TIM1, C6, TimTrig> }
+    dma2::S1 => { TimDmaStream<TIM1, C6, TimCh1> }
+    dma2::S2 => { TimDmaStream<TIM1, C6, TimCh2> }
+    dma2::S3 => { TimDmaStream<TIM1, C6, TimCh1> }
+    dma2::S4 => {
+        TimDmaStream<TIM1, C6, TimCh4>, TimDmaStream<TIM1, C6, TimTrig>,
+        TimDmaStream<TIM1, C6, TimCom>
+    }
+    dma2::S5 => { TimDmaStream<TIM1, C6, TimUp> }
+    dma2::S6 => { TimDmaStream<TIM1, C6, TimCh3> }
+
+    dma2::S1 => { TimDmaStream<TIM8, C7, TimUp> }
+    dma2::S2 => { TimDmaStream<TIM8, C7, TimCh1> }
+    dma2::S3 => { TimDmaStream<TIM8, C7, TimCh2> }
+    dma2::S4 => { TimDmaStream<TIM8, C7, TimCh3> }
+    dma2::S5 => { SpiDmaStream<SPI5, C7, DmaRx> }
+    dma2::S6 => { SpiDmaStream<SPI5, C7, DmaTx> }
+    dma2::S7 => {
+        TimDmaStream<TIM8, C7, TimCh4>, TimDmaStream<TIM8, C7, TimTrig>,
+        TimDmaStream<TIM8, C7, TimCom>
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -95,24 +381,35 @@ pub trait DmaStream {
 
     /// Transfer is complete?
     fn is_complete(&self) -> bool;
+    /// First half of the transfer is complete?
+    fn is_half_complete(&self) -> bool;
     /// Transfer has error?
     fn has_error(&self) -> bool;
+    /// Number of data items left to transfer (NDTR)
+    fn remaining(&self) -> usize;
     /// Reset after a transfer
+    ///
+    /// Disables the stream and waits until it has stopped before
+    /// clearing its flags.
     fn reset(&mut self);
 }
 
 /// DMA stream that can start DMA transfer `X`
 pub trait DmaStreamTransfer<S, T, X: Transfer<Self>>: DmaStream + Sized {
-    /// Start DMA transfer
-    fn start_transfer<CHANNEL: DmaChannel>(self, source: S, target: &mut T) -> X;
+    /// Start DMA transfer to the peripheral register at `target`
+    fn start_transfer<CHANNEL: DmaChannel>(self, source: S, target: *mut T) -> X;
 }
 
 /// DMA transfer
 pub trait Transfer<STREAM>: Sized {
     /// Transfer is complete?
     fn is_complete(&self) -> bool;
+    /// First half of the transfer is complete?
+    fn is_half_complete(&self) -> bool;
     /// Transfer has error?
     fn has_error(&self) -> bool;
+    /// Number of data items left to transfer
+    fn remaining(&self) -> usize;
     /// Reset after a transfer
     ///
     /// Consumes the finished transfer and returns the stream.
@@ -129,6 +426,185 @@ pub trait Transfer<STREAM>: Sized {
     }
 }
 
+/// DMA transfer that can be stopped before it has completed
+pub trait AbortTransfer<STREAM, S>: Transfer<STREAM> {
+    /// Disable the stream and wait until it has stopped.
+    ///
+    /// Returns the stream, the buffer, and the number of data items
+    /// that have been transferred. This can also be used on a
+    /// completed transfer to get the buffer back.
+    fn abort(self) -> (STREAM, S, usize);
+}
+
+/// DMA stream that can start peripheral-to-memory DMA transfer `X`
+pub trait DmaStreamReceive<T, B, X>: DmaStream + Sized {
+    /// Start DMA transfer from the peripheral register `source`
+    fn start_receive<CHANNEL: DmaChannel>(self, source: &T, target: B) -> X;
+}
+
+/// DMA stream that can start a circular peripheral-to-memory transfer
+pub trait DmaStreamCircular<T>: CircularSource + DmaStream + Sized {
+    /// Start a circular DMA transfer from the peripheral register
+    /// `source` into `buffer`
+    fn start_circular<CHANNEL: DmaChannel>(self, source: &T, buffer: &'static mut [T]) -> CircularReader<Self, T>;
+}
+
+/// Write position of a circular DMA transfer
+///
+/// Implemented by all DMA streams; `CircularReader` relies on nothing
+/// else so that it can be used with a mock.
+pub trait CircularSource {
+    /// Number of data items left until the DMA wraps around (NDTR)
+    fn ndt(&self) -> usize;
+    /// Read and clear the transfer complete flag that is set each time
+    /// the DMA wraps around
+    fn take_wrapped(&mut self) -> bool;
+    /// Read and clear the half transfer flag that is set each time the
+    /// DMA passes the middle of the buffer
+    fn take_half(&mut self) -> bool;
+}
+
+/// Memory-to-peripheral source that repeats a single value
+///
+/// Used with `DmaStreamTransfer` to clock out dummy data, for example.
+pub struct Fill<T: 'static> {
+    value: &'static T,
+    len: usize,
+}
+
+impl<T> Fill<T> {
+    /// Transfer `value` `len` times
+    pub fn new(value: &'static T, len: usize) -> Self {
+        Fill { value, len }
+    }
+}
+
+/// Ring buffer reader on top of a circular DMA transfer
+///
+/// The DMA writes into the buffer continuously; the read position is
+/// kept here and the write position is derived from NDTR.
+pub struct CircularReader<STREAM, T: 'static> {
+    stream: STREAM,
+    buffer: &'static mut [T],
+    /// Read index into `buffer`
+    read: usize,
+    /// Write index into `buffer` when NDTR was last read
+    write: usize,
+    /// Data items written by the DMA and not consumed yet; more than
+    /// `buffer.len()` means an overrun
+    unread: usize,
+}
+
+impl<STREAM: CircularSource, T> CircularReader<STREAM, T> {
+    /// Wrap an already running circular transfer into `buffer`
+    pub fn new(stream: STREAM, buffer: &'static mut [T]) -> Self {
+        CircularReader {
+            stream,
+            buffer,
+            read: 0,
+            write: 0,
+            unread: 0,
+        }
+    }
+
+    /// Current write index of the DMA into `buffer`
+    fn write_index(&self) -> usize {
+        let len = self.buffer.len();
+        (len - self.stream.ndt()) % len
+    }
+
+    /// Account for the data written since the last call and return
+    /// the number of data items that can be read
+    fn available(&mut self) -> Result<usize, Error> {
+        let len = self.buffer.len();
+        let mut write = self.write_index();
+        let half = self.stream.take_half();
+        let wrapped = self.stream.take_wrapped();
+        if half || wrapped {
+            // Read again in case the flag was set right after the
+            // first read
+            write = self.write_index();
+        }
+
+        // The shortest progress from the last write index, which is
+        // the actual one unless the flags show that the DMA passed a
+        // point that this progress doesn't reach. In that case it has
+        // gone around the whole buffer at least once more.
+        let last = self.write;
+        let mut progress = (write + len - last) % len;
+        let passes = |point: usize| {
+            (last < point && point <= last + progress)
+                || (last < point + len && point + len <= last + progress)
+        };
+        if (wrapped && !passes(len)) || (half && !passes(len / 2) && !passes((len + 1) / 2)) {
+            progress += len;
+        }
+        self.write = write;
+        self.unread = self.unread.saturating_add(progress);
+
+        // Don't let reads of the buffer be moved before the NDTR read
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        if self.unread > len {
+            Err(Error::Overrun)
+        } else {
+            Ok(self.unread)
+        }
+    }
+
+    /// The data that has been written by the DMA but not yet consumed
+    ///
+    /// Because the buffer wraps around, the data may be split into two
+    /// slices; the second one is empty if it is not.
+    ///
+    /// An overrun may go unnoticed if the DMA writes more than one and
+    /// a half buffers between two calls.
+    pub fn readable(&mut self) -> Result<(&[T], &[T]), Error> {
+        let available = self.available()?;
+        let len = self.buffer.len();
+        let end = self.read + available;
+        if end <= len {
+            Ok((&self.buffer[self.read..end], &[]))
+        } else {
+            Ok((&self.buffer[self.read..], &self.buffer[..end - len]))
+        }
+    }
+
+    /// Mark `n` data items as read
+    ///
+    /// Returns `Error::InvalidLength` if `n` exceeds the total length
+    /// last returned by `readable()`.
+    pub fn consume(&mut self, n: usize) -> Result<(), Error> {
+        let len = self.buffer.len();
+        if self.unread > len {
+            return Err(Error::Overrun);
+        }
+        if n > self.unread {
+            return Err(Error::InvalidLength);
+        }
+        self.read = (self.read + n) % len;
+        self.unread -= n;
+        Ok(())
+    }
+
+    /// Drop all unread data, recovering from `Error::Overrun`
+    pub fn discard(&mut self) {
+        self.stream.take_half();
+        self.stream.take_wrapped();
+        self.write = self.write_index();
+        self.read = self.write;
+        self.unread = 0;
+    }
+}
+
+impl<STREAM: CircularSource + DmaStream, T> CircularReader<STREAM, T> {
+    /// Stop the transfer, returning the stream and the buffer
+    pub fn free(mut self) -> (STREAM, &'static mut [T]) {
+        self.stream.reset();
+        (self.stream, self.buffer)
+    }
+}
+
 
 macro_rules! dma {
     ($($DMAX:ident: ($dmaX:ident, $dmaXen:ident, $dmaXrst:ident, {
@@ -141,8 +617,8 @@ macro_rules! dma {
             $m1arX:ident: $M1ARX:ident,
             $isr:ident: $ISR:ident,
             $ifcr:ident: $IFCR:ident,
-            $tcif:ident, $teif:ident,
-            $ctcif:ident, $cteif:ident,
+            $tcif:ident, $htif:ident, $teif:ident,
+            $ctcif:ident, $chtif:ident, $cteif:ident,
         ),)+
     }),)+) => {
         $(
@@ -150,9 +626,11 @@ macro_rules! dma {
             pub mod $dmaX {
                 use stm32f429::{$DMAX, dma2};
 
+                use cortex_m::interrupt;
                 use rcc::AHB1;
-                use dma::{DmaExt, DmaStream, DmaStreamTransfer, DmaChannel,
-                          Event, data_size};
+                use dma::{DmaExt, DmaStream, DmaStreamTransfer, DmaStreamReceive,
+                          DmaStreamCircular, DmaChannel, DmaInterrupt, CircularSource,
+                          CircularReader, Event, Fill, data_size};
 
                 /// The numbered DMA streams of a device that you can
                 /// use separately.
@@ -183,14 +661,40 @@ macro_rules! dma {
                             unsafe { &(*$DMAX::ptr()).$crX }
                         }
 
+                        /// Modify the control register in a critical
+                        /// section, as `on_interrupt()` modifies it too
+                        fn modify_cr<F>(&mut self, f: F)
+                        where
+                            for<'w> F: FnOnce(&dma2::$crX::R, &'w mut dma2::$crX::W) -> &'w mut dma2::$crX::W,
+                        {
+                            let cr = self.cr();
+                            interrupt::free(|_| cr.modify(f))
+                        }
+
                         fn ndtr(&mut self) -> &dma2::$NDTRX {
                             unsafe { &(*$DMAX::ptr()).$ndtrX }
                         }
 
-                        // fn get_ndtr(&self) -> u32 {
-                        //     // NOTE(unsafe) atomic read with no side effects
-                        //     unsafe { (*$DMAX::ptr()).$ndtrX.read().bits() }
-                        // }
+                        fn get_ndtr(&self) -> u32 {
+                            // NOTE(unsafe) atomic read with no side effects
+                            unsafe { (*$DMAX::ptr()).$ndtrX.read().bits() }
+                        }
+
+                        /// Read and clear the transfer complete flag
+                        fn take_complete(&mut self) -> bool {
+                            let complete = self.isr().$tcif().bit();
+                            if complete {
+                                self.ifcr().write(|w| w.$ctcif().set_bit());
+                            }
+                            complete
+                        }
+
+                        /// Disable the stream and wait until the
+                        /// current data item has been transferred.
+                        fn stop(&mut self) {
+                            self.modify_cr(|_, w| w.en().clear_bit());
+                            while self.cr().read().en().bit_is_set() {}
+                        }
 
                         fn par(&mut self) -> &dma2::$PARX {
                             unsafe { &(*$DMAX::ptr()).$parX }
@@ -208,9 +712,12 @@ macro_rules! dma {
                     impl DmaStream for $SX {
                         fn listen(&mut self, event: Event) {
                             match event {
-                                Event::HalfTransfer => self.cr().modify(|_, w| w.htie().set_bit()),
+                                Event::HalfTransfer => self.modify_cr(|_, w| w.htie().set_bit()),
                                 Event::TransferComplete => {
-                                    self.cr().modify(|_, w| w.tcie().set_bit())
+                                    self.modify_cr(|_, w| w.tcie().set_bit())
+                                }
+                                Event::TransferError => {
+                                    self.modify_cr(|_, w| w.teie().set_bit())
                                 }
                             }
                         }
@@ -218,10 +725,13 @@ macro_rules! dma {
                         fn unlisten(&mut self, event: Event) {
                             match event {
                                 Event::HalfTransfer => {
-                                    self.cr().modify(|_, w| w.htie().clear_bit())
+                                    self.modify_cr(|_, w| w.htie().clear_bit())
                                 },
                                 Event::TransferComplete => {
-                                    self.cr().modify(|_, w| w.tcie().clear_bit())
+                                    self.modify_cr(|_, w| w.tcie().clear_bit())
+                                }
+                                Event::TransferError => {
+                                    self.modify_cr(|_, w| w.teie().clear_bit())
                                 }
                             }
                         }
@@ -230,17 +740,27 @@ macro_rules! dma {
                             self.isr().$tcif().bit()
                         }
 
+                        fn is_half_complete(&self) -> bool {
+                            self.isr().$htif().bit()
+                        }
+
                         fn has_error(&self) -> bool {
                             self.isr().$teif().bit()
                         }
 
+                        fn remaining(&self) -> usize {
+                            self.get_ndtr() as usize
+                        }
+
                         fn reset(&mut self) {
-                            // Disable Stream
-                            self.cr().modify(|_, w| w.en().clear_bit());
-                            
+                            // Disable Stream; it must not be reprogrammed
+                            // before EN reads back as 0
+                            self.stop();
+
                             // Clear status bits
                             self.ifcr().modify(|_, w| {
                                 w.$ctcif().set_bit()
+                                    .$chtif().set_bit()
                                     .$cteif().set_bit()
                             });
                         }
@@ -248,10 +768,10 @@ macro_rules! dma {
                     
                     impl<'s, S> DmaStreamTransfer<(&'s [S], &'s [S]), S, $sx::DoubleBufferedTransfer<S>> for $SX {
                         /// Configure, enable, and return a double-buffered DMA transfer.
-                        fn start_transfer<CHANNEL: DmaChannel>(mut self, (source0, source1): (&'s [S], &'s [S]), target: &mut S) -> $sx::DoubleBufferedTransfer<S> {
+                        fn start_transfer<CHANNEL: DmaChannel>(mut self, (source0, source1): (&'s [S], &'s [S]), target: *mut S) -> $sx::DoubleBufferedTransfer<S> {
                             assert_eq!(source0.len(), source1.len());
 
-                            self.cr().modify(|_, w| unsafe {
+                            self.modify_cr(|_, w| unsafe {
                                 w.msize().bits(data_size::<S>())
                                     .minc().set_bit()
                                     .psize().bits(data_size::<S>())
@@ -270,20 +790,20 @@ macro_rules! dma {
                             self.m1ar().write(|w| unsafe { w.bits(source1_addr) });
                             let source_len = source0.len() as u32;
                             self.ndtr().write(|w| unsafe { w.bits(source_len) });
-                            let target_addr = target as *const _ as u32;
+                            let target_addr = target as u32;
                             self.par().write(|w| unsafe { w.bits(target_addr) });
 
                             // Enable Stream
-                            self.cr().modify(|_, w| w.en().set_bit());
+                            self.modify_cr(|_, w| w.en().set_bit());
 
-                            $sx::DoubleBufferedTransfer::new(self)
+                            $sx::DoubleBufferedTransfer::new(self, source0.len())
                         }
                     }
 
                     impl<T, S: AsRef<[T]>> DmaStreamTransfer<S, T, $sx::OneShotTransfer<S>> for $SX {
                         /// Configure, enable, and return a double-buffered DMA transfer.
-                        fn start_transfer<CHANNEL: DmaChannel>(mut self, source: S, target: &mut T) -> $sx::OneShotTransfer<S> {
-                            self.cr().modify(|_, w| unsafe {
+                        fn start_transfer<CHANNEL: DmaChannel>(mut self, source: S, target: *mut T) -> $sx::OneShotTransfer<S> {
+                            self.modify_cr(|_, w| unsafe {
                                 w.msize().bits(data_size::<T>())
                                     .minc().set_bit()
                                     .psize().bits(data_size::<T>())
@@ -300,26 +820,209 @@ macro_rules! dma {
                             self.m0ar().write(|w| unsafe { w.bits(source_addr) });
                             let source_len = source.as_ref().len() as u32;
                             self.ndtr().write(|w| unsafe { w.bits(source_len) });
-                            let target_addr = target as *const _ as u32;
+                            let target_addr = target as u32;
                             self.par().write(|w| unsafe { w.bits(target_addr) });
 
                             // Enable Stream
-                            self.cr().modify(|_, w| w.en().set_bit());
+                            self.modify_cr(|_, w| w.en().set_bit());
 
-                            $sx::OneShotTransfer::new(self, source)
+                            $sx::OneShotTransfer::new(self, source, source_len as usize)
                         }
                     }
 
-                    /// Contains the `DoubleBufferedTransfer` and the `OneShotTransfer` for `$SX`
+                    impl<T> DmaStreamTransfer<Fill<T>, T, $sx::OneShotTransfer<Fill<T>>> for $SX {
+                        /// Configure, enable, and return a one-shot DMA
+                        /// transfer of a repeated value.
+                        fn start_transfer<CHANNEL: DmaChannel>(mut self, source: Fill<T>, target: *mut T) -> $sx::OneShotTransfer<Fill<T>> {
+                            // Clear stale status bits
+                            self.reset();
+
+                            self.modify_cr(|_, w| unsafe {
+                                w.msize().bits(data_size::<T>())
+                                    // Repeat the same memory location
+                                    .minc().clear_bit()
+                                    .psize().bits(data_size::<T>())
+                                    .pinc().clear_bit()
+                                    .dbm().clear_bit()
+                                    .ct().clear_bit()
+                                    .circ().clear_bit()
+                                    // Memory to peripheral
+                                    .dir().bits(0b01)
+                                    .chsel().bits(CHANNEL::channel())
+                            });
+
+                            let source_addr = source.value as *const T as u32;
+                            self.m0ar().write(|w| unsafe { w.bits(source_addr) });
+                            let source_len = source.len as u32;
+                            self.ndtr().write(|w| unsafe { w.bits(source_len) });
+                            let target_addr = target as u32;
+                            self.par().write(|w| unsafe { w.bits(target_addr) });
+
+                            // Enable Stream
+                            self.modify_cr(|_, w| w.en().set_bit());
+
+                            $sx::OneShotTransfer::new(self, source, source_len as usize)
+                        }
+                    }
+
+                    impl CircularSource for $SX {
+                        fn ndt(&self) -> usize {
+                            self.get_ndtr() as usize
+                        }
+
+                        fn take_wrapped(&mut self) -> bool {
+                            self.take_complete()
+                        }
+
+                        fn take_half(&mut self) -> bool {
+                            let half = self.isr().$htif().bit();
+                            if half {
+                                self.ifcr().write(|w| w.$chtif().set_bit());
+                            }
+                            half
+                        }
+                    }
+
+                    impl<T> DmaStreamReceive<T, &'static mut [T], $sx::OneShotTransfer<&'static mut [T]>> for $SX {
+                        /// Configure, enable, and return a one-shot
+                        /// receiving DMA transfer.
+                        fn start_receive<CHANNEL: DmaChannel>(mut self, source: &T, target: &'static mut [T]) -> $sx::OneShotTransfer<&'static mut [T]> {
+                            // Clear stale status bits
+                            self.reset();
+
+                            self.modify_cr(|_, w| unsafe {
+                                w.msize().bits(data_size::<T>())
+                                    .minc().set_bit()
+                                    .psize().bits(data_size::<T>())
+                                    .pinc().clear_bit()
+                                    .dbm().clear_bit()
+                                    .ct().clear_bit()
+                                    .circ().clear_bit()
+                                    // Peripheral to memory
+                                    .dir().bits(0b00)
+                                    .chsel().bits(CHANNEL::channel())
+                            });
+
+                            let target_addr = target.as_ptr() as u32;
+                            self.m0ar().write(|w| unsafe { w.bits(target_addr) });
+                            let target_len = target.len() as u32;
+                            self.ndtr().write(|w| unsafe { w.bits(target_len) });
+                            let source_addr = source as *const _ as u32;
+                            self.par().write(|w| unsafe { w.bits(source_addr) });
+
+                            // Enable Stream
+                            self.modify_cr(|_, w| w.en().set_bit());
+
+                            $sx::OneShotTransfer::new(self, target, target_len as usize)
+                        }
+                    }
+
+                    impl<T> DmaStreamReceive<T, (&'static mut [T], &'static mut [T]), $sx::DoubleBufferedReceive<T>> for $SX {
+                        /// Configure, enable, and return a double-buffered
+                        /// receiving DMA transfer.
+                        ///
+                        /// The transfer complete interrupt is enabled.
+                        fn start_receive<CHANNEL: DmaChannel>(mut self, source: &T, (target0, target1): (&'static mut [T], &'static mut [T])) -> $sx::DoubleBufferedReceive<T> {
+                            assert_eq!(target0.len(), target1.len());
+
+                            // Clear stale status bits
+                            self.reset();
+
+                            self.modify_cr(|_, w| unsafe {
+                                w.msize().bits(data_size::<T>())
+                                    .minc().set_bit()
+                                    .psize().bits(data_size::<T>())
+                                    .pinc().clear_bit()
+                                    .dbm().set_bit()
+                                    .ct().clear_bit()
+                                    .circ().set_bit()
+                                    // Peripheral to memory
+                                    .dir().bits(0b00)
+                                    .chsel().bits(CHANNEL::channel())
+                            });
+
+                            let target0_addr = target0.as_ptr() as u32;
+                            self.m0ar().write(|w| unsafe { w.bits(target0_addr) });
+                            let target1_addr = target1.as_ptr() as u32;
+                            self.m1ar().write(|w| unsafe { w.bits(target1_addr) });
+                            let target_len = target0.len() as u32;
+                            self.ndtr().write(|w| unsafe { w.bits(target_len) });
+                            let source_addr = source as *const _ as u32;
+                            self.par().write(|w| unsafe { w.bits(source_addr) });
+
+                            self.listen(Event::TransferComplete);
+                            // Enable Stream
+                            self.modify_cr(|_, w| w.en().set_bit());
+
+                            $sx::DoubleBufferedReceive::new(self, target0, target1)
+                        }
+                    }
+
+                    impl<T> DmaStreamCircular<T> for $SX {
+                        /// Configure, enable, and return a circular DMA transfer.
+                        fn start_circular<CHANNEL: DmaChannel>(mut self, source: &T, buffer: &'static mut [T]) -> CircularReader<$SX, T> {
+                            // Clear stale status bits
+                            self.reset();
+
+                            self.modify_cr(|_, w| unsafe {
+                                w.msize().bits(data_size::<T>())
+                                    .minc().set_bit()
+                                    .psize().bits(data_size::<T>())
+                                    .pinc().clear_bit()
+                                    .dbm().clear_bit()
+                                    .ct().clear_bit()
+                                    .circ().set_bit()
+                                    // Peripheral to memory
+                                    .dir().bits(0b00)
+                                    .chsel().bits(CHANNEL::channel())
+                            });
+
+                            let buffer_addr = buffer.as_ptr() as u32;
+                            self.m0ar().write(|w| unsafe { w.bits(buffer_addr) });
+                            let buffer_len = buffer.len() as u32;
+                            self.ndtr().write(|w| unsafe { w.bits(buffer_len) });
+                            let source_addr = source as *const _ as u32;
+                            self.par().write(|w| unsafe { w.bits(source_addr) });
+
+                            // Enable Stream
+                            self.modify_cr(|_, w| w.en().set_bit());
+
+                            CircularReader::new(self, buffer)
+                        }
+                    }
+
+                    /// Contains the `DoubleBufferedTransfer`, `DoubleBufferedReceive`
+                    /// and the `OneShotTransfer` for `$SX`
                     pub mod $sx {
+                        use core::future::Future;
                         use core::marker::PhantomData;
-                        use dma::{DmaStream, Transfer, DoubleBuffer};
+                        use core::mem::swap;
+                        use core::pin::Pin;
+                        use core::task::{Context, Poll};
+                        use dma::{DmaStream, Transfer, AbortTransfer, DoubleBuffer, Error,
+                                  Event, StreamWaker};
                         use super::$SX;
 
+                        /// Waker of the task awaiting a transfer on `$SX`
+                        pub(crate) static WAKER: StreamWaker = StreamWaker::new();
+
+                        impl $SX {
+                            /// Register the task's waker and enable the
+                            /// interrupts that will wake it
+                            fn register(&mut self, cx: &mut Context) {
+                                WAKER.register(cx.waker(), || {
+                                    self.listen(Event::TransferComplete);
+                                    self.listen(Event::TransferError);
+                                });
+                            }
+                        }
+
                         /// Double-buffered DMA transfer
                         pub struct DoubleBufferedTransfer<S> {
                             /// So that `poll()` can detect a buffer switch
                             sent: [bool; 2],
+                            /// Length of each buffer
+                            len: usize,
                             _source_el: PhantomData<S>,
                             stream: $SX,
                         }
@@ -329,10 +1032,18 @@ macro_rules! dma {
                                 self.stream.is_complete()
                             }
 
+                            fn is_half_complete(&self) -> bool {
+                                self.stream.is_half_complete()
+                            }
+
                             fn has_error(&self) -> bool {
                                 self.stream.has_error()
                             }
 
+                            fn remaining(&self) -> usize {
+                                self.stream.remaining()
+                            }
+
                             fn reset(mut self) -> $SX {
                                 self.stream.reset();
                                 self.stream
@@ -344,14 +1055,32 @@ macro_rules! dma {
                             /// returned by `start_transfer` which
                             /// configures and enables the stream
                             /// before.
-                            pub fn new<'s>(stream: $SX) -> Self {
+                            pub fn new<'s>(stream: $SX, len: usize) -> Self {
                                 Self {
                                     sent: [false; 2],
+                                    len,
                                     _source_el: PhantomData,
                                     stream,
                                 }
                             }
 
+                            /// Number of data items of the front buffer
+                            /// that have already been sent
+                            pub fn transferred(&self) -> usize {
+                                self.len - self.remaining()
+                            }
+
+                            /// Disable the stream and wait until it has stopped.
+                            ///
+                            /// Returns the stream and the number of data
+                            /// items of the front buffer that have been sent.
+                            pub fn abort(mut self) -> ($SX, usize) {
+                                self.stream.stop();
+                                let transferred = self.transferred();
+                                self.stream.reset();
+                                (self.stream, transferred)
+                            }
+
                             /// Return the index of the buffer currently being sent
                             #[inline]
                             fn front_buffer(&mut self) -> DoubleBuffer {
@@ -401,9 +1130,144 @@ macro_rules! dma {
                             }
                         }
 
+                        /// Resolves when the back buffer can be written
+                        impl<S: Unpin> Future for DoubleBufferedTransfer<S> {
+                            type Output = Result<(), Error>;
+
+                            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                                let transfer = self.get_mut();
+                                if transfer.has_error() {
+                                    return Poll::Ready(Err(Error::Transfer))
+                                }
+                                if transfer.writable() {
+                                    return Poll::Ready(Ok(()))
+                                }
+
+                                // The complete flag is set on every
+                                // buffer switch, clear it so that only
+                                // the next one raises the interrupt.
+                                transfer.stream.take_complete();
+                                transfer.stream.register(cx);
+
+                                if transfer.writable() {
+                                    Poll::Ready(Ok(()))
+                                } else {
+                                    Poll::Pending
+                                }
+                            }
+                        }
+
+                        /// Double-buffered peripheral-to-memory DMA transfer
+                        ///
+                        /// Each time the DMA has filled one buffer, it
+                        /// switches to the other one. The filled buffer
+                        /// is then handed to the application, which
+                        /// supplies a fresh one in exchange.
+                        pub struct DoubleBufferedReceive<T: 'static> {
+                            /// Buffers at `M0AR` and `M1AR`
+                            buffers: [&'static mut [T]; 2],
+                            stream: $SX,
+                        }
+
+                        impl<T> DoubleBufferedReceive<T> {
+                            /// Construct a new DMA transfer state,
+                            /// returned by `start_receive` which
+                            /// configures and enables the stream
+                            /// before.
+                            pub fn new(stream: $SX, target0: &'static mut [T], target1: &'static mut [T]) -> Self {
+                                Self {
+                                    buffers: [target0, target1],
+                                    stream,
+                                }
+                            }
+
+                            /// Return the index of the buffer currently being filled
+                            #[inline]
+                            fn front_buffer(&mut self) -> DoubleBuffer {
+                                if self.stream.cr().read().ct().bit() {
+                                    DoubleBuffer::Memory1
+                                } else {
+                                    DoubleBuffer::Memory0
+                                }
+                            }
+
+                            /// Transfer has error?
+                            pub fn has_error(&self) -> bool {
+                                self.stream.has_error()
+                            }
+
+                            /// Call from the transfer complete interrupt
+                            /// handler.
+                            ///
+                            /// If the DMA has filled a buffer, installs
+                            /// `spare` for it to fill next and hands the
+                            /// filled buffer back through `spare`.
+                            /// Returns `Ok(true)` if the buffers have
+                            /// been swapped.
+                            ///
+                            /// Returns `Error::InvalidLength` without
+                            /// swapping if `spare` differs in length from
+                            /// the transfer's buffers, and
+                            /// `Error::Overrun` if the DMA has finished
+                            /// the other buffer as well before `spare`
+                            /// could be installed, in which case `spare`
+                            /// may already have been overwritten.
+                            pub fn on_transfer_complete(&mut self, spare: &mut &'static mut [T]) -> Result<bool, Error> {
+                                if self.has_error() {
+                                    return Err(Error::Transfer)
+                                }
+                                if spare.len() != self.buffers[0].len() {
+                                    return Err(Error::InvalidLength)
+                                }
+                                if ! self.stream.take_complete() {
+                                    return Ok(false)
+                                }
+
+                                let front = self.front_buffer();
+                                let back = ! front;
+                                let next_addr = spare.as_ptr() as u32;
+                                match back {
+                                    DoubleBuffer::Memory0 =>
+                                        self.stream.m0ar().write(|w| unsafe { w.bits(next_addr) }),
+                                    DoubleBuffer::Memory1 =>
+                                        self.stream.m1ar().write(|w| unsafe { w.bits(next_addr) }),
+                                }
+                                swap(&mut self.buffers[back as usize], spare);
+
+                                if self.front_buffer() as usize != front as usize ||
+                                    self.stream.is_complete()
+                                {
+                                    Err(Error::Overrun)
+                                } else {
+                                    Ok(true)
+                                }
+                            }
+
+                            /// Disable the stream and wait until it has stopped.
+                            ///
+                            /// Returns the stream and both buffers, the
+                            /// one that was being filled first, along
+                            /// with the number of data items it has
+                            /// received.
+                            pub fn abort(mut self) -> ($SX, (&'static mut [T], &'static mut [T]), usize) {
+                                self.stream.stop();
+                                let front = self.front_buffer();
+                                let transferred = self.buffers[0].len() - self.stream.remaining();
+                                self.stream.reset();
+                                let [buffer0, buffer1] = self.buffers;
+                                let buffers = match front {
+                                    DoubleBuffer::Memory0 => (buffer0, buffer1),
+                                    DoubleBuffer::Memory1 => (buffer1, buffer0),
+                                };
+                                (self.stream, buffers, transferred)
+                            }
+                        }
+
                         /// One-shot DMA transfer
                         pub struct OneShotTransfer<S> {
                             source: S,
+                            /// Number of data items at start
+                            len: usize,
                             stream: $SX,
                         }
 
@@ -412,10 +1276,18 @@ macro_rules! dma {
                                 self.stream.is_complete()
                             }
 
+                            fn is_half_complete(&self) -> bool {
+                                self.stream.is_half_complete()
+                            }
+
                             fn has_error(&self) -> bool {
                                 self.stream.has_error()
                             }
 
+                            fn remaining(&self) -> usize {
+                                self.stream.remaining()
+                            }
+
                             fn reset(mut self) -> $SX {
                                 drop(self.source);
                                 self.stream.reset();
@@ -423,18 +1295,66 @@ macro_rules! dma {
                             }
                         }
 
+                        /// Resolves when the transfer is complete or
+                        /// has failed
+                        ///
+                        /// Await `&mut transfer` to keep the transfer
+                        /// for `reset()` or `abort()` afterwards.
+                        impl<S: Unpin> Future for OneShotTransfer<S> {
+                            type Output = Result<(), Error>;
+
+                            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                                let transfer = self.get_mut();
+                                if transfer.has_error() {
+                                    return Poll::Ready(Err(Error::Transfer))
+                                }
+                                if transfer.is_complete() {
+                                    return Poll::Ready(Ok(()))
+                                }
+
+                                transfer.stream.register(cx);
+
+                                // Check again in case the transfer
+                                // finished before the interrupts were
+                                // enabled
+                                if transfer.has_error() {
+                                    Poll::Ready(Err(Error::Transfer))
+                                } else if transfer.is_complete() {
+                                    Poll::Ready(Ok(()))
+                                } else {
+                                    Poll::Pending
+                                }
+                            }
+                        }
+
+                        impl<S> AbortTransfer<$SX, S> for OneShotTransfer<S> {
+                            fn abort(mut self) -> ($SX, S, usize) {
+                                self.stream.stop();
+                                let transferred = self.transferred();
+                                self.stream.reset();
+                                (self.stream, self.source, transferred)
+                            }
+                        }
+
                         impl<S> OneShotTransfer<S> {
                             /// Construct a new DMA transfer state,
                             /// returned by `start_transfer` which
                             /// configures and enables the stream
                             /// before.
-                            pub fn new<'s>(stream: $SX, source: S) -> Self {
+                            pub fn new<'s>(stream: $SX, source: S, len: usize) -> Self {
                                 Self {
                                     source,
+                                    len,
                                     stream,
                                 }
                             }
 
+                            /// Number of data items that have already
+                            /// been transferred
+                            pub fn transferred(&self) -> usize {
+                                self.len - self.remaining()
+                            }
+
                             /// debug
                             pub fn status(&mut self) -> u32 {
                                 self.stream.cr().read().bits()
@@ -443,6 +1363,31 @@ macro_rules! dma {
                     }
                 )+
 
+                impl DmaInterrupt for $DMAX {
+                    fn on_interrupt() {
+                        // NOTE(unsafe) the stream handles modify the
+                        // control registers only in critical sections
+                        let dma = unsafe { &*$DMAX::ptr() };
+                        $(
+                            let isr = dma.$isr.read();
+                            let cr = dma.$crX.read();
+                            if (isr.$tcif().bit() && cr.tcie().bit()) ||
+                                (isr.$teif().bit() && cr.teie().bit())
+                            {
+                                let waker = $sx::WAKER.take(|| {
+                                    dma.$crX.modify(|_, w| {
+                                        w.tcie().clear_bit()
+                                            .teie().clear_bit()
+                                    });
+                                });
+                                if let Some(waker) = waker {
+                                    waker.wake();
+                                }
+                            }
+                        )+
+                    }
+                }
+
                 impl DmaExt for $DMAX {
                     type Streams = Streams;
 
@@ -477,8 +1422,8 @@ dma! {
             s0m1ar: S0M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif0, teif0,
-            ctcif0, cteif0,
+            tcif0, htif0, teif0,
+            ctcif0, chtif0, cteif0,
         ),
         S1: (
             s1,
@@ -489,8 +1434,8 @@ dma! {
             s1m1ar: S1M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif1, teif1,
-            ctcif1, cteif1,
+            tcif1, htif1, teif1,
+            ctcif1, chtif1, cteif1,
         ),
         S2: (
             s2,
@@ -501,8 +1446,8 @@ dma! {
             s2m1ar: S2M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif2, teif2,
-            ctcif2, cteif2,
+            tcif2, htif2, teif2,
+            ctcif2, chtif2, cteif2,
         ),
         S3: (
             s3,
@@ -513,8 +1458,8 @@ dma! {
             s3m1ar: S3M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif3, teif3,
-            ctcif3, cteif3,
+            tcif3, htif3, teif3,
+            ctcif3, chtif3, cteif3,
         ),
         S4: (
             s4,
@@ -525,8 +1470,8 @@ dma! {
             s4m1ar: S4M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif4, teif4,
-            ctcif4, cteif4,
+            tcif4, htif4, teif4,
+            ctcif4, chtif4, cteif4,
         ),
         S5: (
             s5,
@@ -537,8 +1482,8 @@ dma! {
             s5m1ar: S5M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif5, teif5,
-            ctcif5, cteif5,
+            tcif5, htif5, teif5,
+            ctcif5, chtif5, cteif5,
         ),
         S6: (
             s6,
@@ -549,8 +1494,8 @@ dma! {
             s6m1ar: S6M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif6, teif6,
-            ctcif6, cteif6,
+            tcif6, htif6, teif6,
+            ctcif6, chtif6, cteif6,
         ),
         S7: (
             s7,
@@ -561,8 +1506,8 @@ dma! {
             s7m1ar: S7M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif7, teif7,
-            ctcif7, cteif7,
+            tcif7, htif7, teif7,
+            ctcif7, chtif7, cteif7,
         ),
     }),
     DMA2: (dma2, dma2en, dma2rst, {
@@ -575,8 +1520,8 @@ dma! {
             s0m1ar: S0M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif0, teif0,
-            ctcif0, cteif0,
+            tcif0, htif0, teif0,
+            ctcif0, chtif0, cteif0,
         ),
         S1: (
             s1,
@@ -587,8 +1532,8 @@ dma! {
             s1m1ar: S1M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif1, teif1,
-            ctcif1, cteif1,
+            tcif1, htif1, teif1,
+            ctcif1, chtif1, cteif1,
         ),
         S2: (
             s2,
@@ -599,8 +1544,8 @@ dma! {
             s2m1ar: S2M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif2, teif2,
-            ctcif2, cteif2,
+            tcif2, htif2, teif2,
+            ctcif2, chtif2, cteif2,
         ),
         S3: (
             s3,
@@ -611,8 +1556,8 @@ dma! {
             s3m1ar: S3M1AR,
             lisr: LISR,
             lifcr: LIFCR,
-            tcif3, teif3,
-            ctcif3, cteif3,
+            tcif3, htif3, teif3,
+            ctcif3, chtif3, cteif3,
         ),
         S4: (
             s4,
@@ -623,8 +1568,8 @@ dma! {
             s4m1ar: S4M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif4, teif4,
-            ctcif4, cteif4,
+            tcif4, htif4, teif4,
+            ctcif4, chtif4, cteif4,
         ),
         S5: (
             s5,
@@ -635,8 +1580,8 @@ dma! {
             s5m1ar: S5M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif5, teif5,
-            ctcif5, cteif5,
+            tcif5, htif5, teif5,
+            ctcif5, chtif5, cteif5,
         ),
         S6: (
             s6,
@@ -647,8 +1592,8 @@ dma! {
             s6m1ar: S6M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif6, teif6,
-            ctcif6, cteif6,
+            tcif6, htif6, teif6,
+            ctcif6, chtif6, cteif6,
         ),
         S7: (
             s7,
@@ -659,8 +1604,8 @@ dma! {
             s7m1ar: S7M1AR,
             hisr: HISR,
             hifcr: HIFCR,
-            tcif7, teif7,
-            ctcif7, cteif7,
+            tcif7, htif7, teif7,
+            ctcif7, chtif7, cteif7,
         ),
     }),
 }
@@ -673,3 +1618,133 @@ fn data_size<T>() -> u8 {
         _ => panic!("No such data size"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::vec;
+    use super::{CircularReader, CircularSource, Error};
+
+    /// Stands in for a circular transfer into a buffer of `len` items
+    struct MockSource {
+        len: usize,
+        /// Total number of data items written
+        written: usize,
+        half: bool,
+        wrapped: bool,
+    }
+
+    impl CircularSource for MockSource {
+        fn ndt(&self) -> usize {
+            self.len - self.written % self.len
+        }
+
+        fn take_wrapped(&mut self) -> bool {
+            let wrapped = self.wrapped;
+            self.wrapped = false;
+            wrapped
+        }
+
+        fn take_half(&mut self) -> bool {
+            let half = self.half;
+            self.half = false;
+            half
+        }
+    }
+
+    fn reader(len: usize) -> CircularReader<MockSource, u8> {
+        let buffer = Box::leak(vec![0; len].into_boxed_slice());
+        let source = MockSource { len, written: 0, half: false, wrapped: false };
+        CircularReader::new(source, buffer)
+    }
+
+    /// Let the DMA write the next `n` values of a counter
+    fn write(reader: &mut CircularReader<MockSource, u8>, n: usize) {
+        let len = reader.buffer.len();
+        for _ in 0..n {
+            let stream = &mut reader.stream;
+            reader.buffer[stream.written % len] = stream.written as u8;
+            stream.written += 1;
+            match stream.written % len {
+                0 => stream.wrapped = true,
+                i if i == len / 2 => stream.half = true,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn wraparound() {
+        let mut reader = reader(8);
+        write(&mut reader, 6);
+        assert_eq!(reader.readable(), Ok((&[0, 1, 2, 3, 4, 5][..], &[][..])));
+        assert_eq!(reader.consume(6), Ok(()));
+
+        write(&mut reader, 5);
+        assert_eq!(reader.readable(), Ok((&[6, 7][..], &[8, 9, 10][..])));
+        assert_eq!(reader.consume(5), Ok(()));
+        assert_eq!(reader.readable(), Ok((&[][..], &[][..])));
+    }
+
+    #[test]
+    fn full_buffer() {
+        let mut reader = reader(8);
+        write(&mut reader, 3);
+        assert_eq!(reader.readable().map(|(a, b)| a.len() + b.len()), Ok(3));
+        assert_eq!(reader.consume(3), Ok(()));
+
+        write(&mut reader, 8);
+        assert_eq!(reader.readable(), Ok((&[3, 4, 5, 6, 7][..], &[8, 9, 10][..])));
+    }
+
+    #[test]
+    fn overrun() {
+        let mut reader = reader(8);
+        write(&mut reader, 6);
+        assert!(reader.readable().is_ok());
+        write(&mut reader, 3);
+        assert_eq!(reader.readable(), Err(Error::Overrun));
+        assert_eq!(reader.consume(1), Err(Error::Overrun));
+
+        reader.discard();
+        assert_eq!(reader.readable(), Ok((&[][..], &[][..])));
+        write(&mut reader, 2);
+        assert_eq!(reader.readable(), Ok((&[9, 10][..], &[][..])));
+    }
+
+    #[test]
+    fn overrun_over_several_laps() {
+        let mut reader = reader(8);
+        write(&mut reader, 2);
+        assert!(reader.readable().is_ok());
+        assert_eq!(reader.consume(2), Ok(()));
+
+        // Two whole laps and one item, ending up right after the
+        // previous write index
+        write(&mut reader, 17);
+        assert_eq!(reader.readable(), Err(Error::Overrun));
+
+        // One lap and a few items, ending up before the previous write
+        // index
+        let mut reader = self::reader(8);
+        write(&mut reader, 6);
+        assert!(reader.readable().is_ok());
+        assert_eq!(reader.consume(6), Ok(()));
+        write(&mut reader, 12);
+        assert_eq!(reader.readable(), Err(Error::Overrun));
+    }
+
+    #[test]
+    fn partial_consume() {
+        let mut reader = reader(8);
+        write(&mut reader, 5);
+        assert!(reader.readable().is_ok());
+        assert_eq!(reader.consume(2), Ok(()));
+        assert_eq!(reader.readable(), Ok((&[2, 3, 4][..], &[][..])));
+        assert_eq!(reader.consume(4), Err(Error::InvalidLength));
+        assert_eq!(reader.consume(3), Ok(()));
+        assert_eq!(reader.readable(), Ok((&[][..], &[][..])));
+    }
+}
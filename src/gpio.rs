@@ -99,7 +99,7 @@ macro_rules! gpio {
 
             use rcc::AHB1;
             use super::{
-                AF0, AF4, AF5, AF6, AF7, AF11, Floating, GpioExt, Input, OpenDrain, Output,
+                AF0, AF4, AF5, AF6, AF7, AF8, AF11, Floating, GpioExt, Input, OpenDrain, Output,
                 PullDown, PullUp, PushPull,
             };
 
@@ -329,6 +329,30 @@ macro_rules! gpio {
                         $PXi { _mode: PhantomData }
                     }
 
+                    /// Configures the pin to serve as alternate function 8 (AF8)
+                    pub fn into_af8(
+                        self,
+                        moder: &mut MODER,
+                        afr: &mut $AFR,
+                    ) -> $PXi<AF8> {
+                        let offset = 2 * $i;
+
+                        // alternate function mode
+                        let mode = 0b10;
+                        moder.moder().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                        });
+
+                        let af = 8;
+                        let offset = 4 * ($i % 8);
+
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | (af << offset))
+                        });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
                     /// Configures the pin to operate as a floating input pin
                     pub fn into_floating_input(
                         self,
@@ -693,7 +717,7 @@ gpio!(GPIOF, gpiof, gpiok, gpiofen, gpiofrst, PFx, [
     PF5: (pf5, 5, Input<Floating>, AFRL),
     PF6: (pf6, 6, Input<Floating>, AFRL),
     PF7: (pf7, 7, Input<Floating>, AFRL),
-    PF8: (pf8, 8, Input<Floating>, AFRL),
+    PF8: (pf8, 8, Input<Floating>, AFRH),
     PF9: (pf9, 9, Input<Floating>, AFRH),
     PF10: (pf10, 10, Input<Floating>, AFRH),
     PF11: (pf11, 11, Input<Floating>, AFRH),
@@ -712,7 +736,7 @@ gpio!(GPIOG, gpiog, gpiok, gpiogen, gpiogrst, PGx, [
     PG5: (pg5, 5, Input<Floating>, AFRL),
     PG6: (pg6, 6, Input<Floating>, AFRL),
     PG7: (pg7, 7, Input<Floating>, AFRL),
-    PG8: (pg8, 8, Input<Floating>, AFRL),
+    PG8: (pg8, 8, Input<Floating>, AFRH),
     PG9: (pg9, 9, Input<Floating>, AFRH),
     PG10: (pg10, 10, Input<Floating>, AFRH),
     PG11: (pg11, 11, Input<Floating>, AFRH),
@@ -731,7 +755,7 @@ gpio!(GPIOH, gpioh, gpiok, gpiohen, gpiohrst, PHx, [
     PH5: (ph5, 5, Input<Floating>, AFRL),
     PH6: (ph6, 6, Input<Floating>, AFRL),
     PH7: (ph7, 7, Input<Floating>, AFRL),
-    PH8: (ph8, 8, Input<Floating>, AFRL),
+    PH8: (ph8, 8, Input<Floating>, AFRH),
     PH9: (ph9, 9, Input<Floating>, AFRH),
     PH10: (ph10, 10, Input<Floating>, AFRH),
     PH11: (ph11, 11, Input<Floating>, AFRH),
@@ -750,7 +774,7 @@ gpio!(GPIOI, gpioi, gpiok, gpioien, gpioirst, PIx, [
     PI5: (pi5, 5, Input<Floating>, AFRL),
     PI6: (pi6, 6, Input<Floating>, AFRL),
     PI7: (pi7, 7, Input<Floating>, AFRL),
-    PI8: (pi8, 8, Input<Floating>, AFRL),
+    PI8: (pi8, 8, Input<Floating>, AFRH),
     PI9: (pi9, 9, Input<Floating>, AFRH),
     PI10: (pi10, 10, Input<Floating>, AFRH),
     PI11: (pi11, 11, Input<Floating>, AFRH),
@@ -769,7 +793,7 @@ gpio!(GPIOI, gpioi, gpiok, gpioien, gpioirst, PIx, [
 //     PJ5: (pj5, 5, Input<Floating>, AFRL),
 //     PJ6: (pj6, 6, Input<Floating>, AFRL),
 //     PJ7: (pj7, 7, Input<Floating>, AFRL),
-//     PJ8: (pj8, 8, Input<Floating>, AFRL),
+//     PJ8: (pj8, 8, Input<Floating>, AFRH),
 //     PJ9: (pj9, 9, Input<Floating>, AFRH),
 //     PJ10: (pj10, 10, Input<Floating>, AFRH),
 //     PJ11: (pj11, 11, Input<Floating>, AFRH),
@@ -788,7 +812,7 @@ gpio!(GPIOI, gpioi, gpiok, gpioien, gpioirst, PIx, [
 //     PK5: (pk5, 5, Input<Floating>, AFRL),
 //     PK6: (pk6, 6, Input<Floating>, AFRL),
 //     PK7: (pk7, 7, Input<Floating>, AFRL),
-//     PK8: (pk8, 8, Input<Floating>, AFRL),
+//     PK8: (pk8, 8, Input<Floating>, AFRH),
 //     PK9: (pk9, 9, Input<Floating>, AFRH),
 //     PK10: (pk10, 10, Input<Floating>, AFRH),
 //     PK11: (pk11, 11, Input<Floating>, AFRH),
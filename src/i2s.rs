@@ -9,7 +9,7 @@ use gpio::gpioc::{PC2, PC3, PC6, PC7};
 use gpio::{AF5, AF6};
 use rcc::{APB1, APB2, Clocks};
 // use time::{KiloHertz, MegaHertz};
-use dma::*;
+pub use dma::{DmaRx, DmaTx, I2sDmaStream};
 
 /// SD: Serial Data (mapped on the MOSI pin) to transmit or receive
 /// the two time- multiplexed data channels (in half-duplex mode
@@ -58,26 +58,6 @@ unsafe impl MckPin<SPI2> for PC6<AF5> {}
 unsafe impl MckPin<SPI3> for PC7<AF6> {}
 // TODO: continue after PC7
 
-/// Rx direction
-pub struct DmaRx;
-/// Tx direction
-pub struct DmaTx;
-
-/// Possible DMA configuration for an SPI device
-pub unsafe trait I2sDmaStream<STREAM, CHANNEL, DIRECTION> {}
-// DMA: there are only impls for SPI1..3, not 4..6 yet
-unsafe impl I2sDmaStream<SPI3, C0, DmaRx> for dma1::S0 {}
-unsafe impl I2sDmaStream<SPI3, C0, DmaRx> for dma1::S2 {}
-unsafe impl I2sDmaStream<SPI2, C0, DmaRx> for dma1::S3 {}
-unsafe impl I2sDmaStream<SPI2, C0, DmaTx> for dma1::S4 {}
-unsafe impl I2sDmaStream<SPI3, C0, DmaTx> for dma1::S5 {}
-unsafe impl I2sDmaStream<SPI3, C0, DmaTx> for dma1::S7 {}
-unsafe impl I2sDmaStream<SPI1, C3, DmaRx> for dma2::S0 {}
-unsafe impl I2sDmaStream<SPI1, C3, DmaRx> for dma2::S2 {}
-unsafe impl I2sDmaStream<SPI1, C3, DmaTx> for dma2::S3 {}
-unsafe impl I2sDmaStream<SPI1, C3, DmaTx> for dma2::S5 {}
-
-
 /// Slave role (doesn't provide clock)
 pub struct SlaveRole {}
 /// Master role (provides clock)
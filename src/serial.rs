@@ -1,20 +1,183 @@
 //! Serial
 
+use core::fmt;
 use core::ptr;
 use core::marker::PhantomData;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use cortex_m::interrupt;
+use hal::blocking;
+use hal::digital::OutputPin;
 use hal::serial;
+use hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use nb;
-use stm32f429::{USART1, USART2, USART3};
-
-use gpio::gpioa::{PA10, PA2, PA3, PA9};
-use gpio::gpiob::{PB10, PB11, PB6, PB7};
-use gpio::gpioc::{PC10, PC11, PC4, PC5};
-use gpio::gpiod::{PD5, PD6, PD8, PD9, PD12};
-use gpio::gpioe::{PE0, PE1, PE15};
-use gpio::AF7;
+use void::Void;
+use stm32f429::{USART1, USART2, USART3, UART4, UART5, USART6, UART7, UART8};
+
+use gpio::gpioa::{PA0, PA1, PA10, PA11, PA12, PA2, PA3, PA4, PA8, PA9};
+use gpio::gpiob::{PB10, PB11, PB12, PB13, PB14, PB6, PB7};
+use gpio::gpioc::{PC10, PC11, PC12, PC4, PC5, PC6, PC7, PC8};
+use gpio::gpiod::{PD2, PD3, PD4, PD5, PD6, PD7, PD8, PD9, PD10, PD11, PD12};
+use gpio::gpioe::{PE0, PE1, PE7, PE8, PE15};
+use gpio::gpiof::{PF6, PF7};
+use gpio::gpiog::{PG7, PG8, PG9, PG12, PG13, PG14, PG15};
+use gpio::{AF7, AF8};
 use rcc::{APB1, APB2, Clocks};
-use time::Bps;
+use time::{Bps, Hertz, U32Ext};
+use dma::{DmaChannel, DmaStreamTransfer, DmaStreamReceive, DmaStreamCircular,
+          CircularReader, Transfer, AbortTransfer, UsartDmaStream, DmaRx, DmaTx};
+
+/// Number of data bits per frame, not counting the parity bit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordLength {
+    /// 7 data bits, only available with parity
+    DataBits7,
+    /// 8 data bits
+    DataBits8,
+    /// 9 data bits, only available without parity
+    DataBits9,
+}
+
+/// Parity bit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parity {
+    /// No parity bit
+    ParityNone,
+    /// Even parity
+    ParityEven,
+    /// Odd parity
+    ParityOdd,
+}
+
+/// Number of stop bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopBits {
+    /// 1 stop bit
+    Stop1 = 0b00,
+    /// 0.5 stop bits
+    Stop0p5 = 0b01,
+    /// 2 stop bits
+    Stop2 = 0b10,
+    /// 1.5 stop bits
+    Stop1p5 = 0b11,
+}
+
+/// Oversampling of the receiver
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Oversampling {
+    /// 16x oversampling, more tolerant to clock deviation
+    By16,
+    /// 8x oversampling, allows up to `pclk / 8` baud
+    By8,
+}
+
+/// Serial configuration
+///
+/// Defaults to 115200 baud, 8N1, and 16x oversampling.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    baud_rate: Bps,
+    word_length: WordLength,
+    parity: Parity,
+    stop_bits: StopBits,
+    oversampling: Oversampling,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            baud_rate: 115_200.bps(),
+            word_length: WordLength::DataBits8,
+            parity: Parity::ParityNone,
+            stop_bits: StopBits::Stop1,
+            oversampling: Oversampling::By16,
+        }
+    }
+}
+
+impl From<Bps> for Config {
+    fn from(baud_rate: Bps) -> Self {
+        Config::default().baud_rate(baud_rate)
+    }
+}
+
+impl Config {
+    /// Sets the baud rate
+    pub fn baud_rate(mut self, baud_rate: Bps) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Sets the number of data bits
+    pub fn word_length(mut self, word_length: WordLength) -> Self {
+        self.word_length = word_length;
+        self
+    }
+
+    /// Sets the parity
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Sets the number of stop bits
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Sets the oversampling
+    pub fn oversampling(mut self, oversampling: Oversampling) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    /// Value of the `M` (9-bit frame) bit in `CR1`
+    fn m(&self) -> bool {
+        match (self.word_length, self.parity) {
+            (WordLength::DataBits7, Parity::ParityNone) =>
+                panic!("7 data bits require a parity bit"),
+            (WordLength::DataBits7, _) => false,
+            (WordLength::DataBits8, Parity::ParityNone) => false,
+            (WordLength::DataBits8, _) => true,
+            (WordLength::DataBits9, Parity::ParityNone) => true,
+            (WordLength::DataBits9, _) =>
+                panic!("9 data bits are not possible with a parity bit"),
+        }
+    }
+
+    /// Computes the `BRR` value for the peripheral clock `pclk`,
+    /// returning it along with the actual baud rate
+    fn brr(&self, pclk: u32) -> (u32, Bps) {
+        let baud_rate = self.baud_rate.0;
+        // USARTDIV times the oversampling, rounded to the nearest
+        // value
+        let div = (pclk + baud_rate / 2) / baud_rate;
+        let brr = match self.oversampling {
+            Oversampling::By16 => {
+                assert!(div >= 16 && div <= 0xFFFF, "impossible baud rate");
+                div
+            }
+            Oversampling::By8 => {
+                assert!(div >= 8 && div <= 0x7FFF, "impossible baud rate");
+                // The fraction has only 3 bits, and BRR[3] must stay clear
+                ((div & !0b111) << 1) | (div & 0b111)
+            }
+        };
+        let actual = (pclk + div / 2) / div;
+        (brr, Bps(actual))
+    }
+}
+
+/// Length of the break that is detected in LIN mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinBreakLength {
+    /// 10-bit break detection
+    Bits10,
+    /// 11-bit break detection
+    Bits11,
+}
 
 /// Interrupt event
 pub enum Event {
@@ -22,6 +185,16 @@ pub enum Event {
     Rxne,
     /// New data can be sent
     Txe,
+    /// The line has become idle after receiving data
+    Idle,
+    /// The last frame has been sent completely
+    TransmissionComplete,
+    /// Parity error
+    ParityError,
+    /// LIN break detected, only with LIN mode enabled
+    LinBreak,
+    /// The CTS input has changed, USART1-3 and USART6 only
+    Cts,
 }
 
 /// Serial error
@@ -35,9 +208,31 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
+    /// Break condition, a framing error with all data bits low
+    Break,
     #[doc(hidden)] _Extensible,
 }
 
+/// Snapshot of the receiver error flags
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Errors {
+    /// Framing error
+    pub framing: bool,
+    /// Noise error
+    pub noise: bool,
+    /// RX buffer overrun
+    pub overrun: bool,
+    /// Parity check error
+    pub parity: bool,
+}
+
+impl Errors {
+    /// Returns `true` if any error flag is set
+    pub fn any(&self) -> bool {
+        self.framing || self.noise || self.overrun || self.parity
+    }
+}
+
 // FIXME these should be "closed" traits
 /// TX pin - DO NOT IMPLEMENT THIS TRAIT
 pub unsafe trait TxPin<USART> {}
@@ -51,6 +246,64 @@ pub unsafe trait RtsPin<USART> {}
 /// CTS pin - DO NOT IMPLEMENT THIS TRAIT
 pub unsafe trait CtsPin<USART> {}
 
+/// CK pin - DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait CkPin<USART> {}
+
+/// Pins of a `Serial` - DO NOT IMPLEMENT THIS TRAIT
+///
+/// Implemented for `(TX, RX)` and, with hardware flow control, for
+/// `(TX, RX, RTS, CTS)`.
+pub unsafe trait Pins<USART> {
+    #[doc(hidden)]
+    fn enable_flow_control(usart: &USART);
+}
+
+/// USART with hardware flow control - DO NOT IMPLEMENT THIS TRAIT
+#[doc(hidden)]
+pub unsafe trait FlowControl {
+    #[doc(hidden)]
+    fn enable_flow_control(&self);
+}
+
+/// Enables the `Event::Cts` interrupt, which only the USARTs have
+trait CtsInterrupt {
+    fn listen_cts(&self, enable: bool);
+}
+
+/// The UARTs have no CTS input, so `Event::Cts` is ignored
+macro_rules! no_cts {
+    ($($UARTX:ident),+) => {
+        $(
+            impl CtsInterrupt for $UARTX {
+                fn listen_cts(&self, _: bool) {}
+            }
+        )+
+    }
+}
+
+no_cts!(UART4, UART5, UART7, UART8);
+
+unsafe impl<USART, TX, RX> Pins<USART> for (TX, RX)
+where
+    TX: TxPin<USART>,
+    RX: RxPin<USART>,
+{
+    fn enable_flow_control(_: &USART) {}
+}
+
+unsafe impl<USART, TX, RX, RTS, CTS> Pins<USART> for (TX, RX, RTS, CTS)
+where
+    USART: FlowControl,
+    TX: TxPin<USART>,
+    RX: RxPin<USART>,
+    RTS: RtsPin<USART>,
+    CTS: CtsPin<USART>,
+{
+    fn enable_flow_control(usart: &USART) {
+        usart.enable_flow_control()
+    }
+}
+
 unsafe impl TxPin<USART1> for PA9<AF7> {}
 unsafe impl TxPin<USART1> for PB6<AF7> {}
 unsafe impl TxPin<USART1> for PC4<AF7> {}
@@ -80,13 +333,91 @@ unsafe impl RxPin<USART3> for PC11<AF7> {}
 unsafe impl RxPin<USART3> for PD9<AF7> {}
 unsafe impl RxPin<USART3> for PE15<AF7> {}
 
-// XXX: add more impls
+unsafe impl TxPin<UART4> for PA0<AF8> {}
+unsafe impl TxPin<UART4> for PC10<AF8> {}
+
+unsafe impl RxPin<UART4> for PA1<AF8> {}
+unsafe impl RxPin<UART4> for PC11<AF8> {}
+
+unsafe impl TxPin<UART5> for PC12<AF8> {}
+
+unsafe impl RxPin<UART5> for PD2<AF8> {}
+
+unsafe impl TxPin<USART6> for PC6<AF8> {}
+unsafe impl TxPin<USART6> for PG14<AF8> {}
+
+unsafe impl RxPin<USART6> for PC7<AF8> {}
+unsafe impl RxPin<USART6> for PG9<AF8> {}
+
+unsafe impl TxPin<UART7> for PE8<AF8> {}
+unsafe impl TxPin<UART7> for PF7<AF8> {}
+
+unsafe impl RxPin<UART7> for PE7<AF8> {}
+unsafe impl RxPin<UART7> for PF6<AF8> {}
+
+unsafe impl TxPin<UART8> for PE1<AF8> {}
+
+unsafe impl RxPin<UART8> for PE0<AF8> {}
+
+unsafe impl RtsPin<USART1> for PA12<AF7> {}
+
+unsafe impl CtsPin<USART1> for PA11<AF7> {}
+
+unsafe impl RtsPin<USART2> for PA1<AF7> {}
+unsafe impl RtsPin<USART2> for PD4<AF7> {}
+
+unsafe impl CtsPin<USART2> for PA0<AF7> {}
+unsafe impl CtsPin<USART2> for PD3<AF7> {}
+
+unsafe impl RtsPin<USART3> for PB14<AF7> {}
 unsafe impl RtsPin<USART3> for PD12<AF7> {}
 
+unsafe impl CtsPin<USART3> for PB13<AF7> {}
+unsafe impl CtsPin<USART3> for PD11<AF7> {}
+
+unsafe impl RtsPin<USART6> for PG8<AF8> {}
+unsafe impl RtsPin<USART6> for PG12<AF8> {}
+
+unsafe impl CtsPin<USART6> for PG13<AF8> {}
+unsafe impl CtsPin<USART6> for PG15<AF8> {}
+
+unsafe impl CkPin<USART1> for PA8<AF7> {}
+
+unsafe impl CkPin<USART2> for PA4<AF7> {}
+unsafe impl CkPin<USART2> for PD7<AF7> {}
+
+unsafe impl CkPin<USART3> for PB12<AF7> {}
+unsafe impl CkPin<USART3> for PC12<AF7> {}
+unsafe impl CkPin<USART3> for PD10<AF7> {}
+
+unsafe impl CkPin<USART6> for PC8<AF8> {}
+unsafe impl CkPin<USART6> for PG7<AF8> {}
+
+/// Transmission complete flag in `SR`
+const SR_TC: u32 = 1 << 6;
+/// LIN break detection flag in `SR`
+const SR_LBD: u32 = 1 << 8;
+/// CTS change flag in `SR`, reserved on the UARTs
+const SR_CTS: u32 = 1 << 9;
+
+/// Parity error cleared by `read()`
+const LATCHED_PARITY: usize = 1 << 0;
+/// Framing error cleared by `read()`
+const LATCHED_FRAMING: usize = 1 << 1;
+/// Noise error cleared by `read()`
+const LATCHED_NOISE: usize = 1 << 2;
+/// Overrun cleared by `read()`
+const LATCHED_OVERRUN: usize = 1 << 3;
+/// Overrun that `read()` has yet to report
+const UNREPORTED_OVERRUN: usize = 1 << 4;
+
 /// Serial abstraction
 pub struct Serial<USART, PINS> {
     usart: USART,
     pins: PINS,
+    config: Config,
+    /// Actual baud rate
+    baud_rate: Bps,
 }
 
 /// Serial receiver
@@ -99,45 +430,236 @@ pub struct Tx<USART> {
     _usart: PhantomData<USART>,
 }
 
+/// RS-485 serial that drives the transceiver's driver enable pin
+///
+/// `DE` is asserted before a byte is written and released once the
+/// transmission is complete: by the blocking writes themselves, or,
+/// with the non-blocking `write()`, by `on_interrupt()` on the
+/// `TransmissionComplete` interrupt, which `write()` enables, as well
+/// as by `flush()` and `read()`. Tie the receiver enable pin of the
+/// transceiver to `DE` to suppress the echo.
+pub struct Rs485<USART, PINS, DE> {
+    serial: Serial<USART, PINS>,
+    de: DE,
+}
+
+/// Serial in LIN mode
+pub struct Lin<USART, PINS> {
+    serial: Serial<USART, PINS>,
+}
+
+/// Serial in IrDA SIR mode
+///
+/// The low-power mode is only available on USART1-3 and USART6.
+pub struct Irda<USART, PINS> {
+    serial: Serial<USART, PINS>,
+}
+
+/// Serial in ISO 7816 smartcard mode, USART1-3 and USART6 only
+///
+/// The card is clocked from the `CK` pin and its I/O line is the TX
+/// pin, which should be open-drain with a pull-up. As the line is
+/// shared, transmitted bytes are received back.
+pub struct Smartcard<USART, PINS, CK> {
+    serial: Serial<USART, PINS>,
+    ck: CK,
+}
+
+/// Synchronous USART acting as an SPI master, USART1-3 and USART6 only
+///
+/// The clock is output on the `CK` pin. Unlike most SPI devices, data
+/// is shifted out least significant bit first.
+pub struct Synchronous<USART, PINS> {
+    usart: USART,
+    pins: PINS,
+}
+
+/// Single-producer, single-consumer ring buffer of bytes
+///
+/// One slot is always kept free to tell a full buffer from an empty
+/// one.
+struct RingBuffer {
+    buffer: *mut u8,
+    len: usize,
+    /// Write index
+    head: AtomicUsize,
+    /// Read index
+    tail: AtomicUsize,
+}
+
+impl RingBuffer {
+    fn new(buffer: &'static mut [u8]) -> Self {
+        assert!(buffer.len() >= 2);
+        RingBuffer {
+            buffer: buffer.as_mut_ptr(),
+            len: buffer.len(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Called by the producer only
+    fn push(&self, byte: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % self.len;
+        if next == self.tail.load(Ordering::Acquire) {
+            return false;
+        }
+        // NOTE(unsafe) the consumer doesn't access this slot until
+        // `head` has been advanced
+        unsafe { ptr::write_volatile(self.buffer.add(head), byte) };
+        self.head.store(next, Ordering::Release);
+        true
+    }
+
+    /// Called by the consumer only
+    fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        // NOTE(unsafe) the producer doesn't access this slot until
+        // `tail` has been advanced
+        let byte = unsafe { ptr::read_volatile(self.buffer.add(tail)) };
+        self.tail.store((tail + 1) % self.len, Ordering::Release);
+        Some(byte)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tail.load(Ordering::Acquire) == self.head.load(Ordering::Acquire)
+    }
+
+    fn free(self) -> &'static mut [u8] {
+        // NOTE(unsafe) reconstructs the buffer passed to `new()`
+        unsafe { slice::from_raw_parts_mut(self.buffer, self.len) }
+    }
+}
+
+/// Interrupt-driven serial with transmit and receive ring buffers
+///
+/// `split()` hands out one reader, one writer and one interrupt handle,
+/// each of which can be moved to its own context. The USART interrupt
+/// handler must call `BufferedInterrupt::on_interrupt()`.
+pub struct BufferedSerial<USART, PINS> {
+    serial: Serial<USART, PINS>,
+    rx: RingBuffer,
+    tx: RingBuffer,
+    rx_overflows: AtomicUsize,
+    overruns: AtomicUsize,
+}
+
+// NOTE(unsafe) a shared reference only gives access to the counters,
+// each ring buffer end is owned by a single handle from `split()`
+unsafe impl<USART: Send, PINS: Send> Sync for BufferedSerial<USART, PINS> {}
+
+/// Receiving half of a `BufferedSerial`, the ring buffer consumer
+pub struct BufferedRx<'a, USART: 'a, PINS: 'a> {
+    serial: &'a BufferedSerial<USART, PINS>,
+}
+
+/// Transmitting half of a `BufferedSerial`, the ring buffer producer
+pub struct BufferedTx<'a, USART: 'a, PINS: 'a> {
+    serial: &'a BufferedSerial<USART, PINS>,
+}
+
+/// Interrupt side of a `BufferedSerial`, which fills the receive buffer
+/// and drains the transmit buffer
+pub struct BufferedInterrupt<'a, USART: 'a, PINS: 'a> {
+    serial: &'a BufferedSerial<USART, PINS>,
+}
+
+impl<USART, PINS> BufferedSerial<USART, PINS> {
+    /// Splits into the handles for reading, writing and the interrupt
+    /// handler
+    ///
+    /// Being borrowed mutably, the buffers are never accessed by more
+    /// than one producer and one consumer.
+    pub fn split<'a>(&'a mut self) -> (
+        BufferedRx<'a, USART, PINS>,
+        BufferedTx<'a, USART, PINS>,
+        BufferedInterrupt<'a, USART, PINS>,
+    ) {
+        let serial = &*self;
+        (BufferedRx { serial }, BufferedTx { serial }, BufferedInterrupt { serial })
+    }
+
+    /// Number of received bytes dropped because the receive buffer
+    /// was full
+    pub fn rx_overflows(&self) -> usize {
+        self.rx_overflows.load(Ordering::Relaxed)
+    }
+
+    /// Number of hardware overruns, where bytes were lost because the
+    /// interrupt was not handled in time
+    pub fn overruns(&self) -> usize {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
 macro_rules! hal {
     ($(
         $USARTX:ident: ($usartX:ident, $APB:ident, $usartXen:ident, $usartXrst:ident, $pclkX:ident),
     )+) => {
         $(
-            impl<TX, RX> Serial<$USARTX, (TX, RX)> {
+            impl<PINS> Serial<$USARTX, PINS> {
                 /// Configures a USART peripheral to provide serial communication
-                pub fn $usartX(
+                ///
+                /// `config` can be a full `Config` or only a baud rate
+                /// for 8N1. Passing `(TX, RX, RTS, CTS)` pins enables
+                /// hardware flow control.
+                pub fn $usartX<C>(
                     usart: $USARTX,
-                    pins: (TX, RX),
-                    baud_rate: Bps,
+                    pins: PINS,
+                    config: C,
                     clocks: Clocks,
                     apb: &mut $APB,
                 ) -> Self
                 where
-                    TX: TxPin<$USARTX>,
-                    RX: RxPin<$USARTX>,
+                    C: Into<Config>,
+                    PINS: Pins<$USARTX>,
                 {
+                    let config = config.into();
+
                     // enable or reset $USARTX
                     apb.enr().modify(|_, w| w.$usartXen().set_bit());
                     apb.rstr().modify(|_, w| w.$usartXrst().set_bit());
                     apb.rstr().modify(|_, w| w.$usartXrst().clear_bit());
 
                     // hardware flow control
-                    // TODO enable DMA
-                    // usart.cr3.write(|w| w.rtse().clear_bit().ctse().clear_bit());
+                    PINS::enable_flow_control(&usart);
 
-                    let brr = clocks.$pclkX().0 / baud_rate.0;
-                    assert!(brr >= 16, "impossible baud rate");
+                    let (brr, baud_rate) = config.brr(clocks.$pclkX().0);
                     usart.brr.write(|w| unsafe { w.bits(brr) });
 
+                    usart.cr2.write(|w| unsafe { w.stop().bits(config.stop_bits as u8) });
+
                     // UE: enable USART
                     // RE: enable receiver
                     // TE: enable transceiver
-                    usart
-                        .cr1
-                        .write(|w| w.ue().set_bit().re().set_bit().te().set_bit());
+                    usart.cr1.write(|w| {
+                        w.ue().set_bit()
+                            .re().set_bit()
+                            .te().set_bit()
+                            .m().bit(config.m())
+                            .pce().bit(config.parity != Parity::ParityNone)
+                            .ps().bit(config.parity == Parity::ParityOdd)
+                            .over8().bit(config.oversampling == Oversampling::By8)
+                    });
+
+                    Serial { usart, pins, config, baud_rate }
+                }
+
+                /// Returns the actual baud rate
+                pub fn baud_rate(&self) -> Bps {
+                    self.baud_rate
+                }
 
-                    Serial { usart, pins }
+                /// Returns the deviation of the actual baud rate from
+                /// the requested one in parts per million
+                pub fn baud_error(&self) -> i32 {
+                    let requested = i64::from(self.config.baud_rate.0);
+                    let actual = i64::from(self.baud_rate.0);
+                    ((actual - requested) * 1_000_000 / requested) as i32
                 }
 
                 /// Starts listening for an interrupt event
@@ -149,6 +671,19 @@ macro_rules! hal {
                         Event::Txe => {
                             self.usart.cr1.modify(|_, w| w.txeie().set_bit())
                         },
+                        Event::Idle => {
+                            self.usart.cr1.modify(|_, w| w.idleie().set_bit())
+                        },
+                        Event::TransmissionComplete => {
+                            self.usart.cr1.modify(|_, w| w.tcie().set_bit())
+                        },
+                        Event::ParityError => {
+                            self.usart.cr1.modify(|_, w| w.peie().set_bit())
+                        },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().set_bit())
+                        },
+                        Event::Cts => self.usart.listen_cts(true),
                     }
                 }
 
@@ -161,17 +696,28 @@ macro_rules! hal {
                         Event::Txe => {
                             self.usart.cr1.modify(|_, w| w.txeie().clear_bit())
                         },
+                        Event::Idle => {
+                            self.usart.cr1.modify(|_, w| w.idleie().clear_bit())
+                        },
+                        Event::TransmissionComplete => {
+                            self.usart.cr1.modify(|_, w| w.tcie().clear_bit())
+                        },
+                        Event::ParityError => {
+                            self.usart.cr1.modify(|_, w| w.peie().clear_bit())
+                        },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().clear_bit())
+                        },
+                        Event::Cts => self.usart.listen_cts(false),
                     }
                 }
 
-                /// Enable the RTS pin.
-                pub fn set_rts<RTS: RtsPin<$USARTX>>(&mut self, _rts: RTS) {
-                    self.usart.cr3.modify(|_, w| w.rtse().bit(true));
-                }
-
-                /// Enable the CTS pin.
-                pub fn set_cts<CTS: CtsPin<$USARTX>>(&mut self, _cts: CTS) {
-                    self.usart.cr3.modify(|_, w| w.ctse().bit(true));
+                /// Enable or disable single-wire half-duplex mode
+                ///
+                /// TX and RX are connected internally, and only the TX
+                /// pin is used. It should be pulled up externally.
+                pub fn set_half_duplex(&mut self, enable: bool) {
+                    self.usart.cr3.modify(|_, w| w.hdsel().bit(enable));
                 }
 
                 /// Splits the `Serial` abstraction into a transmitter and a receiver half
@@ -187,48 +733,256 @@ macro_rules! hal {
                 }
 
                 /// Releases the USART peripheral and associated pins
-                pub fn free(self) -> ($USARTX, (TX, RX)) {
+                pub fn free(self) -> ($USARTX, PINS) {
+                    self.usart.cr3.modify(|_, w| w.dmat().clear_bit().dmar().clear_bit());
                     (self.usart, self.pins)
                 }
             }
 
+            impl Rx<$USARTX> {
+                /// Start receiving exactly `buffer.len()` bytes with DMA
+                pub fn read_exact_dma<STREAM, CHANNEL, X>(&mut self, stream: STREAM, buffer: &'static mut [u8]) -> X
+                where
+                    STREAM: DmaStreamReceive<u8, &'static mut [u8], X> + UsartDmaStream<$USARTX, CHANNEL, DmaRx>,
+                    CHANNEL: DmaChannel,
+                    X: Transfer<STREAM>,
+                {
+                    let dr = self.enable_dma_rx();
+                    stream.start_receive::<CHANNEL>(dr, buffer)
+                }
+
+                /// Start receiving continuously into the ring buffer
+                /// `buffer` with circular DMA
+                pub fn read_circular_dma<STREAM, CHANNEL>(&mut self, stream: STREAM, buffer: &'static mut [u8]) -> CircularReader<STREAM, u8>
+                where
+                    STREAM: DmaStreamCircular<u8> + UsartDmaStream<$USARTX, CHANNEL, DmaRx>,
+                    CHANNEL: DmaChannel,
+                {
+                    let dr = self.enable_dma_rx();
+                    stream.start_circular::<CHANNEL>(dr, buffer)
+                }
+
+                /// Start receiving a frame of variable length with DMA
+                ///
+                /// Enables the `Idle` interrupt; call `finish_frame()`
+                /// from its handler to obtain the frame. Frames longer
+                /// than `buffer` are truncated.
+                pub fn read_frame_dma<STREAM, CHANNEL, X>(&mut self, stream: STREAM, buffer: &'static mut [u8]) -> X
+                where
+                    STREAM: DmaStreamReceive<u8, &'static mut [u8], X> + UsartDmaStream<$USARTX, CHANNEL, DmaRx>,
+                    CHANNEL: DmaChannel,
+                    X: AbortTransfer<STREAM, &'static mut [u8]>,
+                {
+                    self.clear_idle();
+                    // NOTE(unsafe) the IDLEIE bit is only modified by
+                    // the receiver half
+                    unsafe { (*$USARTX::ptr()).cr1.modify(|_, w| w.idleie().set_bit()) };
+                    self.read_exact_dma(stream, buffer)
+                }
+
+                /// Stop receiving a frame started with
+                /// `read_frame_dma()` once the line has become idle
+                ///
+                /// Returns the stream, the buffer, and the length of
+                /// the frame.
+                pub fn finish_frame<STREAM, X>(&mut self, transfer: X) -> (STREAM, &'static mut [u8], usize)
+                where
+                    X: AbortTransfer<STREAM, &'static mut [u8]>,
+                {
+                    let (stream, buffer, len) = transfer.abort();
+                    self.disable_dma_rx();
+                    self.clear_idle();
+                    (stream, buffer, len)
+                }
+
+                /// Wait for a transfer started by `read_exact_dma()` and
+                /// stop the DMA requests of the USART
+                pub fn wait_dma<STREAM, X>(&mut self, transfer: X) -> Result<STREAM, STREAM>
+                where
+                    X: Transfer<STREAM>,
+                {
+                    let result = transfer.wait();
+                    self.disable_dma_rx();
+                    result
+                }
+
+                /// Has the line become idle after receiving data?
+                pub fn is_idle(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$USARTX::ptr()).sr.read().idle().bit_is_set() }
+                }
+
+                /// Clear the idle flag
+                ///
+                /// This reads the data register, so any unread byte is
+                /// lost unless it is picked up by DMA.
+                pub fn clear_idle(&mut self) {
+                    // NOTE(unsafe) reading SR, then DR clears IDLE
+                    unsafe {
+                        (*$USARTX::ptr()).sr.read();
+                        ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const u8);
+                    }
+                }
+
+                /// Returns all receiver error flags that are set or
+                /// that `read()` has cleared since the last
+                /// `clear_errors()`
+                pub fn errors(&self) -> Errors {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let sr = unsafe { (*$USARTX::ptr()).sr.read() };
+                    let latched = Rx::<$USARTX>::latched().load(Ordering::Relaxed);
+                    Errors {
+                        framing: sr.fe().bit_is_set() || latched & LATCHED_FRAMING != 0,
+                        noise: sr.nf().bit_is_set() || latched & LATCHED_NOISE != 0,
+                        overrun: sr.ore().bit_is_set() || latched & LATCHED_OVERRUN != 0,
+                        parity: sr.pe().bit_is_set() || latched & LATCHED_PARITY != 0,
+                    }
+                }
+
+                /// Clears all receiver error flags, discarding the
+                /// received byte
+                pub fn clear_errors(&mut self) {
+                    // NOTE(unsafe) reading SR, then DR clears the error flags
+                    unsafe {
+                        (*$USARTX::ptr()).sr.read();
+                        ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const u8);
+                    }
+                    Rx::<$USARTX>::latched().store(0, Ordering::Relaxed);
+                }
+
+                /// Error flags that `read()` has cleared
+                fn latched() -> &'static AtomicUsize {
+                    static LATCHED: AtomicUsize = AtomicUsize::new(0);
+                    &LATCHED
+                }
+
+                /// Let the USART make a DMA request for each received
+                /// byte, returning the data register
+                fn enable_dma_rx(&mut self) -> &u8 {
+                    // NOTE(unsafe) the DMAR bit is only modified by the
+                    // receiver half
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    usart.cr3.modify(|_, w| w.dmar().set_bit());
+                    // NOTE(unsafe) the DMA reads only the lower byte
+                    unsafe { &*(&usart.dr as *const _ as *const u8) }
+                }
+
+                fn disable_dma_rx(&mut self) {
+                    // NOTE(unsafe) the DMAR bit is only modified by the
+                    // receiver half
+                    unsafe { (*$USARTX::ptr()).cr3.modify(|_, w| w.dmar().clear_bit()) };
+                }
+            }
+
+            impl Tx<$USARTX> {
+                /// Start writing `buffer` with DMA
+                pub fn write_all_dma<S, STREAM, CHANNEL, X>(&mut self, stream: STREAM, buffer: S) -> X
+                where
+                    S: AsRef<[u8]>,
+                    STREAM: DmaStreamTransfer<S, u8, X> + UsartDmaStream<$USARTX, CHANNEL, DmaTx>,
+                    CHANNEL: DmaChannel,
+                    X: Transfer<STREAM>,
+                {
+                    // NOTE(unsafe) the DMAT bit and the TC flag are
+                    // only modified by the transmitter half
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    // Clear TC so that `flush()` waits for the end of
+                    // this transfer. The other flags are cleared by
+                    // writing 0 as well, so write 1 to those.
+                    usart.sr.write(|w| unsafe { w.bits(!SR_TC) });
+                    usart.cr3.modify(|_, w| w.dmat().set_bit());
+
+                    let dr = &usart.dr as *const _ as *mut u8;
+                    stream.start_transfer::<CHANNEL>(buffer, dr)
+                }
+
+                /// Wait for a transfer started by `write_all_dma()` and
+                /// stop the DMA requests of the USART
+                pub fn wait_dma<STREAM, X>(&mut self, transfer: X) -> Result<STREAM, STREAM>
+                where
+                    X: Transfer<STREAM>,
+                {
+                    let result = transfer.wait();
+                    // NOTE(unsafe) the DMAT bit is only modified by the
+                    // transmitter half
+                    unsafe { (*$USARTX::ptr()).cr3.modify(|_, w| w.dmat().clear_bit()) };
+                    result
+                }
+            }
+
             impl serial::Read<u8> for Rx<$USARTX> {
                 type Error = Error;
 
+                /// Reports one error per call, the first of parity,
+                /// framing or break, noise and overrun; `errors()`
+                /// returns all of them. On an overrun, the byte received
+                /// before is returned and the overrun is reported by the
+                /// next call.
                 fn read(&mut self) -> nb::Result<u8, Error> {
+                    let latched = Rx::<$USARTX>::latched();
+                    if latched.load(Ordering::Relaxed) & UNREPORTED_OVERRUN != 0 {
+                        latched.fetch_and(!UNREPORTED_OVERRUN, Ordering::Relaxed);
+                        return Err(nb::Error::Other(Error::Overrun));
+                    }
+
                     // NOTE(unsafe) atomic read with no side effects
                     let isr = unsafe { (*$USARTX::ptr()).sr.read() };
+                    let overrun = if isr.ore().bit_is_set() {
+                        LATCHED_OVERRUN | UNREPORTED_OVERRUN
+                    } else {
+                        0
+                    };
 
-                    Err(if isr.rxne().bit_is_set() {
+                    if isr.pe().bit_is_set()
+                        || isr.fe().bit_is_set()
+                        || isr.nf().bit_is_set()
+                    {
+                        // NOTE(read_volatile) reading DR after SR clears the error flags
+                        let byte: u8 = unsafe {
+                            ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _)
+                        };
+
+                        let mut flags = overrun;
+                        if isr.pe().bit_is_set() { flags |= LATCHED_PARITY }
+                        if isr.fe().bit_is_set() { flags |= LATCHED_FRAMING }
+                        if isr.nf().bit_is_set() { flags |= LATCHED_NOISE }
+                        latched.fetch_or(flags, Ordering::Relaxed);
+
+                        Err(nb::Error::Other(if isr.pe().bit_is_set() {
+                            Error::Parity
+                        } else if isr.fe().bit_is_set() {
+                            if byte == 0 { Error::Break } else { Error::Framing }
+                        } else {
+                            Error::Noise
+                        }))
+                    } else if isr.rxne().bit_is_set() {
                         // NOTE(read_volatile) see `write_volatile` below
-                        return Ok(unsafe {
+                        let byte = unsafe {
                             ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _)
-                        });
-                    } else if isr.pe().bit_is_set() {
-                        unsafe { ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _); }
-                        nb::Error::Other(Error::Parity)
-                    } else if isr.fe().bit_is_set() {
-                        unsafe { ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _); }
-                        nb::Error::Other(Error::Framing)
-                    } else if isr.nf().bit_is_set() {
-                        unsafe { ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _); }
-                        nb::Error::Other(Error::Noise)
-                    } else if isr.ore().bit_is_set() {
-                        unsafe { ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _); }
-                        nb::Error::Other(Error::Overrun)
+                        };
+                        // On an overrun, this is the byte received before
+                        latched.fetch_or(overrun, Ordering::Relaxed);
+                        Ok(byte)
+                    } else if overrun != 0 {
+                        // NOTE(read_volatile) reading DR after SR clears the error flags
+                        unsafe {
+                            ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const u8);
+                        }
+                        latched.fetch_or(LATCHED_OVERRUN, Ordering::Relaxed);
+                        Err(nb::Error::Other(Error::Overrun))
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        Err(nb::Error::WouldBlock)
+                    }
                 }
             }
 
             impl serial::Write<u8> for Tx<$USARTX> {
-                // NOTE(!) See section "29.7 USART interrupts"; the only possible errors during transmission
+                // NOTE(Void) See section "29.7 USART interrupts"; the only possible errors during transmission
                 // are: clear to send (which is disabled in this case) errors and framing errors (which only
                 // occur in SmartCard mode); neither of these apply to our hardware configuration
-                type Error = !;
+                type Error = Void;
 
-                fn flush(&mut self) -> nb::Result<(), !> {
+                fn flush(&mut self) -> nb::Result<(), Void> {
                     // NOTE(unsafe) atomic read with no side effects
                     let isr = unsafe { (*$USARTX::ptr()).sr.read() };
 
@@ -239,7 +993,7 @@ macro_rules! hal {
                     }
                 }
 
-                fn write(&mut self, byte: u8) -> nb::Result<(), !> {
+                fn write(&mut self, byte: u8) -> nb::Result<(), Void> {
                     // NOTE(unsafe) atomic read with no side effects
                     let isr = unsafe { (*$USARTX::ptr()).sr.read() };
 
@@ -255,6 +1009,656 @@ macro_rules! hal {
                     }
                 }
             }
+
+            rs485!($USARTX);
+            lin!($USARTX);
+            irda!($USARTX);
+            buffered!($USARTX);
+        )+
+    }
+}
+
+/// Implements RS-485 mode, invoked by `hal!`
+macro_rules! rs485 {
+    ($USARTX:ident) => {
+        impl<PINS> Serial<$USARTX, PINS> {
+            /// Turn into RS-485 mode, driving the transceiver's
+            /// driver enable pin `de`
+            pub fn into_rs485<DE: OutputPin>(self, mut de: DE) -> Rs485<$USARTX, PINS, DE> {
+                de.set_low();
+                Rs485 { serial: self, de }
+            }
+        }
+
+        impl<PINS, DE: OutputPin> Rs485<$USARTX, PINS, DE> {
+            /// Call from the USART interrupt handler to release the
+            /// driver enable pin once the last byte has been sent
+            pub fn on_interrupt(&mut self) {
+                self.release();
+            }
+
+            /// Release the driver enable pin, returning the
+            /// `Serial` and the pin
+            pub fn free(mut self) -> (Serial<$USARTX, PINS>, DE) {
+                self.serial.usart.cr1.modify(|_, w| w.tcie().clear_bit());
+                self.de.set_low();
+                (self.serial, self.de)
+            }
+
+            /// Releases the driver enable pin and stops listening
+            /// for `TransmissionComplete` if the transmission is
+            /// complete
+            fn release(&mut self) -> bool {
+                let usart = &self.serial.usart;
+                if usart.sr.read().tc().bit_is_set() {
+                    usart.cr1.modify(|_, w| w.tcie().clear_bit());
+                    self.de.set_low();
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+
+        impl<PINS, DE: OutputPin> serial::Read<u8> for Rs485<$USARTX, PINS, DE> {
+            type Error = Error;
+
+            /// Releases the driver enable pin first if the
+            /// transmission is complete
+            fn read(&mut self) -> nb::Result<u8, Error> {
+                self.release();
+                serial::Read::read(&mut Rx::<$USARTX> { _usart: PhantomData })
+            }
+        }
+
+        impl<PINS, DE: OutputPin> serial::Write<u8> for Rs485<$USARTX, PINS, DE> {
+            type Error = Void;
+
+            /// Releases the driver enable pin once the last byte
+            /// has been sent
+            fn flush(&mut self) -> nb::Result<(), Void> {
+                if self.release() {
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+
+            /// Asserts the driver enable pin before writing and
+            /// listens for `TransmissionComplete` to release it
+            fn write(&mut self, byte: u8) -> nb::Result<(), Void> {
+                let usart = &self.serial.usart;
+                if usart.sr.read().txe().bit_is_set() {
+                    self.de.set_high();
+                    // NOTE(write_volatile) 8-bit write that's not
+                    // possible through the svd2rust API
+                    unsafe {
+                        ptr::write_volatile(&usart.dr as *const _ as *mut u8, byte)
+                    }
+                    usart.cr1.modify(|_, w| w.tcie().set_bit());
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+        }
+
+        impl<PINS, DE: OutputPin> blocking::serial::Write<u8> for Rs485<$USARTX, PINS, DE> {
+            type Error = Void;
+
+            /// Writes `buffer` and releases the driver enable pin
+            /// once the last byte has been sent
+            fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Void> {
+                for byte in buffer {
+                    while serial::Write::write(self, *byte).is_err() {}
+                }
+                self.bflush()
+            }
+
+            fn bflush(&mut self) -> Result<(), Void> {
+                while serial::Write::flush(self).is_err() {}
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Implements the serial traits for a mode that wraps `Serial`
+macro_rules! mode_io {
+    ($USARTX:ident, $MODE:ident) => {
+        impl<PINS> serial::Read<u8> for $MODE<$USARTX, PINS> {
+            type Error = Error;
+
+            fn read(&mut self) -> nb::Result<u8, Error> {
+                serial::Read::read(&mut Rx::<$USARTX> { _usart: PhantomData })
+            }
+        }
+
+        impl<PINS> serial::Write<u8> for $MODE<$USARTX, PINS> {
+            type Error = Void;
+
+            fn flush(&mut self) -> nb::Result<(), Void> {
+                if self.serial.usart.sr.read().tc().bit_is_set() {
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+
+            fn write(&mut self, byte: u8) -> nb::Result<(), Void> {
+                let usart = &self.serial.usart;
+                if usart.sr.read().txe().bit_is_set() {
+                    // NOTE(write_volatile) 8-bit write that's not
+                    // possible through the svd2rust API
+                    unsafe {
+                        ptr::write_volatile(&usart.dr as *const _ as *mut u8, byte)
+                    }
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+        }
+
+        impl<PINS> blocking::serial::write::Default<u8> for $MODE<$USARTX, PINS> {}
+    }
+}
+
+/// Implements LIN mode, invoked by `hal!`
+macro_rules! lin {
+    ($USARTX:ident) => {
+        impl<PINS> Serial<$USARTX, PINS> {
+            /// Turn into LIN mode
+            ///
+            /// The frame format is forced to 1 stop bit.
+            pub fn into_lin(self, break_length: LinBreakLength) -> Lin<$USARTX, PINS> {
+                self.usart.cr3.modify(|_, w| w.hdsel().clear_bit());
+                self.usart.cr2.modify(|_, w| unsafe {
+                    w.stop().bits(StopBits::Stop1 as u8)
+                        .lbdl().bit(break_length == LinBreakLength::Bits11)
+                        .linen().set_bit()
+                });
+                Lin { serial: self }
+            }
+        }
+
+        impl<PINS> Lin<$USARTX, PINS> {
+            /// Sends a break after the current byte
+            pub fn send_break(&mut self) {
+                self.serial.usart.cr1.modify(|_, w| w.sbk().set_bit());
+            }
+
+            /// Returns `true` if a break has been detected
+            pub fn is_break_detected(&self) -> bool {
+                self.serial.usart.sr.read().lbd().bit_is_set()
+            }
+
+            /// Clears the break detection flag
+            pub fn clear_break(&mut self) {
+                // the other flags are cleared by writing 0 as well
+                self.serial.usart.sr.write(|w| unsafe { w.bits(!SR_LBD) });
+            }
+
+            /// Starts listening for an interrupt event
+            pub fn listen(&mut self, event: Event) {
+                self.serial.listen(event)
+            }
+
+            /// Stops listening for an interrupt event
+            pub fn unlisten(&mut self, event: Event) {
+                self.serial.unlisten(event)
+            }
+
+            /// Leave LIN mode
+            pub fn into_serial(self) -> Serial<$USARTX, PINS> {
+                let serial = self.serial;
+                let stop_bits = serial.config.stop_bits as u8;
+                serial.usart.cr2.modify(|_, w| unsafe {
+                    w.linen().clear_bit().stop().bits(stop_bits)
+                });
+                serial
+            }
+        }
+
+        mode_io!($USARTX, Lin);
+    }
+}
+
+/// Implements IrDA mode, invoked by `hal!`
+macro_rules! irda {
+    ($USARTX:ident) => {
+        impl<PINS> Serial<$USARTX, PINS> {
+            /// Turn into IrDA SIR mode
+            ///
+            /// The frame format is forced to 1 stop bit.
+            pub fn into_irda(self) -> Irda<$USARTX, PINS> {
+                self.usart.cr2.modify(|_, w| unsafe { w.stop().bits(StopBits::Stop1 as u8) });
+                self.usart.cr3.modify(|_, w| {
+                    w.hdsel().clear_bit()
+                        .irlp().clear_bit()
+                        .iren().set_bit()
+                });
+                Irda { serial: self }
+            }
+        }
+
+        impl<PINS> Irda<$USARTX, PINS> {
+            /// Leave IrDA mode
+            pub fn into_serial(self) -> Serial<$USARTX, PINS> {
+                let serial = self.serial;
+                let stop_bits = serial.config.stop_bits as u8;
+                serial.usart.cr3.modify(|_, w| w.iren().clear_bit().irlp().clear_bit());
+                serial.usart.cr2.modify(|_, w| unsafe { w.stop().bits(stop_bits) });
+                serial
+            }
+        }
+
+        mode_io!($USARTX, Irda);
+    }
+}
+
+/// Implements `BufferedSerial`, invoked by `hal!`
+macro_rules! buffered {
+    ($USARTX:ident) => {
+        impl<PINS> Serial<$USARTX, PINS> {
+            /// Turn into an interrupt-driven `BufferedSerial` using
+            /// the given ring buffers
+            ///
+            /// The `Rxne` interrupt is enabled, the interrupt of
+            /// the USART must be unmasked by the application.
+            pub fn buffered(
+                self,
+                rx_buffer: &'static mut [u8],
+                tx_buffer: &'static mut [u8],
+            ) -> BufferedSerial<$USARTX, PINS> {
+                self.usart.cr1.modify(|_, w| w.rxneie().set_bit());
+
+                BufferedSerial {
+                    serial: self,
+                    rx: RingBuffer::new(rx_buffer),
+                    tx: RingBuffer::new(tx_buffer),
+                    rx_overflows: AtomicUsize::new(0),
+                    overruns: AtomicUsize::new(0),
+                }
+            }
+        }
+
+        impl<'a, PINS> BufferedInterrupt<'a, $USARTX, PINS> {
+            /// Move data between the peripheral and the ring
+            /// buffers; call this from the USART interrupt handler
+            pub fn on_interrupt(&mut self) {
+                let serial = self.serial;
+                let usart = &serial.serial.usart;
+                let sr = usart.sr.read();
+
+                if sr.rxne().bit_is_set() || sr.ore().bit_is_set() {
+                    if sr.ore().bit_is_set() {
+                        serial.overruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // NOTE(read_volatile) reading DR after SR also
+                    // clears the error flags
+                    let byte = unsafe {
+                        ptr::read_volatile(&usart.dr as *const _ as *const u8)
+                    };
+                    if sr.rxne().bit_is_set() && ! serial.rx.push(byte) {
+                        serial.rx_overflows.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                if sr.txe().bit_is_set() && usart.cr1.read().txeie().bit_is_set() {
+                    match serial.tx.pop() {
+                        Some(byte) => unsafe {
+                            ptr::write_volatile(&usart.dr as *const _ as *mut u8, byte)
+                        },
+                        None => usart.cr1.modify(|_, w| w.txeie().clear_bit()),
+                    }
+                }
+            }
+        }
+
+        impl<'a, PINS> BufferedRx<'a, $USARTX, PINS> {
+            /// Read a received byte from the buffer
+            pub fn read(&mut self) -> nb::Result<u8, Void> {
+                self.serial.rx.pop().ok_or(nb::Error::WouldBlock)
+            }
+
+            /// Wait for a received byte
+            pub fn read_blocking(&mut self) -> u8 {
+                loop {
+                    if let Some(byte) = self.serial.rx.pop() {
+                        return byte;
+                    }
+                }
+            }
+        }
+
+        impl<'a, PINS> BufferedTx<'a, $USARTX, PINS> {
+            /// Put a byte into the transmit buffer
+            pub fn write(&mut self, byte: u8) -> nb::Result<(), Void> {
+                if ! self.serial.tx.push(byte) {
+                    return Err(nb::Error::WouldBlock);
+                }
+                // Let the interrupt handler send it
+                let usart = &self.serial.serial.usart;
+                interrupt::free(|_| {
+                    usart.cr1.modify(|_, w| w.txeie().set_bit())
+                });
+                Ok(())
+            }
+
+            /// Put all `bytes` into the transmit buffer, waiting
+            /// for free space as needed
+            pub fn write_all(&mut self, bytes: &[u8]) {
+                for byte in bytes {
+                    while self.write(*byte).is_err() {}
+                }
+            }
+
+            /// Has everything in the transmit buffer been sent?
+            pub fn flush(&mut self) -> nb::Result<(), Void> {
+                if self.serial.tx.is_empty() && self.serial.serial.usart.sr.read().tc().bit_is_set() {
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+        }
+
+        impl<PINS> BufferedSerial<$USARTX, PINS> {
+            /// Disable the interrupts, returning the `Serial` and
+            /// the ring buffers
+            pub fn free(self) -> (Serial<$USARTX, PINS>, &'static mut [u8], &'static mut [u8]) {
+                self.serial.usart.cr1.modify(|_, w| {
+                    w.rxneie().clear_bit()
+                        .txeie().clear_bit()
+                });
+                (self.serial, self.rx.free(), self.tx.free())
+            }
+        }
+
+        impl<'a, PINS> serial::Read<u8> for BufferedRx<'a, $USARTX, PINS> {
+            type Error = Void;
+
+            fn read(&mut self) -> nb::Result<u8, Void> {
+                BufferedRx::<$USARTX, PINS>::read(self)
+            }
+        }
+
+        impl<'a, PINS> serial::Write<u8> for BufferedTx<'a, $USARTX, PINS> {
+            type Error = Void;
+
+            fn write(&mut self, byte: u8) -> nb::Result<(), Void> {
+                BufferedTx::<$USARTX, PINS>::write(self, byte)
+            }
+
+            fn flush(&mut self) -> nb::Result<(), Void> {
+                BufferedTx::<$USARTX, PINS>::flush(self)
+            }
+        }
+
+        impl<'a, PINS> blocking::serial::write::Default<u8> for BufferedTx<'a, $USARTX, PINS> {}
+
+        impl<'a, PINS> fmt::Write for BufferedTx<'a, $USARTX, PINS> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.write_all(s.as_bytes());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Features of the USARTs that the UARTs lack
+macro_rules! usart {
+    ($(
+        $USARTX:ident: ($usartX:ident, $APB:ident, $usartXen:ident, $usartXrst:ident, $pclkX:ident),
+    )+) => {
+        $(
+            impl<TX, RX, CK> Synchronous<$USARTX, (TX, RX, CK)> {
+                /// Configures a USART peripheral as a synchronous SPI master
+                ///
+                /// The clock pulse of the last data bit is only output
+                /// if `last_bit_clock` is set.
+                pub fn $usartX<F>(
+                    usart: $USARTX,
+                    pins: (TX, RX, CK),
+                    mode: Mode,
+                    last_bit_clock: bool,
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                    TX: TxPin<$USARTX>,
+                    RX: RxPin<$USARTX>,
+                    CK: CkPin<$USARTX>,
+                {
+                    let config = Config::default()
+                        .baud_rate(Bps(freq.into().0))
+                        .oversampling(Oversampling::By8);
+
+                    // enable or reset $USARTX
+                    apb.enr().modify(|_, w| w.$usartXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$usartXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$usartXrst().clear_bit());
+
+                    let (brr, _) = config.brr(clocks.$pclkX().0);
+                    usart.brr.write(|w| unsafe { w.bits(brr) });
+
+                    usart.cr2.write(|w| {
+                        w.clken().set_bit()
+                            .cpol().bit(mode.polarity == Polarity::IdleHigh)
+                            .cpha().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .lbcl().bit(last_bit_clock)
+                    });
+
+                    usart.cr1.write(|w| {
+                        w.ue().set_bit()
+                            .re().set_bit()
+                            .te().set_bit()
+                            .over8().set_bit()
+                    });
+
+                    Synchronous { usart, pins }
+                }
+
+                /// Releases the USART peripheral and associated pins
+                pub fn free(self) -> ($USARTX, (TX, RX, CK)) {
+                    (self.usart, self.pins)
+                }
+            }
+
+            impl<PINS> FullDuplex<u8> for Synchronous<$USARTX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    serial::Read::read(&mut Rx::<$USARTX> { _usart: PhantomData })
+                }
+
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    if self.usart.sr.read().txe().bit_is_set() {
+                        // NOTE(write_volatile) 8-bit write that's not
+                        // possible through the svd2rust API
+                        unsafe {
+                            ptr::write_volatile(&self.usart.dr as *const _ as *mut u8, byte)
+                        }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl<PINS> blocking::spi::transfer::Default<u8> for Synchronous<$USARTX, PINS> {}
+
+            impl<PINS> blocking::spi::write::Default<u8> for Synchronous<$USARTX, PINS> {}
+
+            unsafe impl FlowControl for $USARTX {
+                fn enable_flow_control(&self) {
+                    self.cr3.modify(|_, w| w.rtse().set_bit().ctse().set_bit());
+                }
+            }
+
+            impl CtsInterrupt for $USARTX {
+                fn listen_cts(&self, enable: bool) {
+                    self.cr3.modify(|_, w| w.ctsie().bit(enable));
+                }
+            }
+
+            impl<TX, RX> Serial<$USARTX, (TX, RX)> {
+                /// Enable the RTS pin.
+                pub fn set_rts<RTS: RtsPin<$USARTX>>(&mut self, _rts: RTS) {
+                    self.usart.cr3.modify(|_, w| w.rtse().set_bit());
+                }
+
+                /// Enable the CTS pin.
+                pub fn set_cts<CTS: CtsPin<$USARTX>>(&mut self, _cts: CTS) {
+                    self.usart.cr3.modify(|_, w| w.ctse().set_bit());
+                }
+            }
+
+            impl<PINS> Serial<$USARTX, PINS> {
+                /// Returns `true` if the CTS input has changed since the
+                /// last call, clearing the flag
+                pub fn take_cts_change(&mut self) -> bool {
+                    if self.usart.sr.read().cts().bit_is_set() {
+                        // the other flags are cleared by writing 0 as well
+                        self.usart.sr.write(|w| unsafe { w.bits(!SR_CTS) });
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+
+            impl<PINS> Serial<$USARTX, PINS> {
+                /// Turn into IrDA SIR low-power mode
+                ///
+                /// Pulses last 3 periods of the low-power clock, which
+                /// is `pclk` divided by `prescaler`, instead of 3/16 of
+                /// a bit period.
+                pub fn into_irda_low_power(self, prescaler: u8) -> Irda<$USARTX, PINS> {
+                    assert!(prescaler != 0);
+                    self.usart.gtpr.modify(|_, w| unsafe { w.psc().bits(prescaler) });
+                    let irda = self.into_irda();
+                    irda.serial.usart.cr3.modify(|_, w| w.irlp().set_bit());
+                    irda
+                }
+
+                /// Turn into smartcard mode, clocking the card from `ck`
+                ///
+                /// The card clock is `pclk / (2 * prescaler)`, and
+                /// `guard_time` is the number of bit periods between
+                /// transmitted characters. The frame format is forced
+                /// to 8 data bits with even parity and 1.5 stop bits.
+                pub fn into_smartcard<CK>(
+                    self,
+                    ck: CK,
+                    prescaler: u8,
+                    guard_time: u8,
+                ) -> Smartcard<$USARTX, PINS, CK>
+                where
+                    CK: CkPin<$USARTX>,
+                {
+                    assert!(prescaler != 0 && prescaler < 32);
+                    self.usart.gtpr.write(|w| unsafe {
+                        w.psc().bits(prescaler).gt().bits(guard_time)
+                    });
+                    self.usart.cr1.modify(|_, w| {
+                        w.m().set_bit().pce().set_bit().ps().clear_bit()
+                    });
+                    self.usart.cr2.modify(|_, w| unsafe {
+                        w.linen().clear_bit()
+                            .stop().bits(StopBits::Stop1p5 as u8)
+                            .clken().set_bit()
+                    });
+                    self.usart.cr3.modify(|_, w| {
+                        w.hdsel().clear_bit()
+                            .iren().clear_bit()
+                            .nack().set_bit()
+                            .scen().set_bit()
+                    });
+                    Smartcard { serial: self, ck }
+                }
+            }
+
+            impl<PINS, CK> Smartcard<$USARTX, PINS, CK> {
+                /// Sets the guard time in bit periods
+                pub fn set_guard_time(&mut self, guard_time: u8) {
+                    self.serial.usart.gtpr.modify(|_, w| unsafe { w.gt().bits(guard_time) });
+                }
+
+                /// Sets the prescaler of the card clock
+                pub fn set_prescaler(&mut self, prescaler: u8) {
+                    assert!(prescaler != 0 && prescaler < 32);
+                    self.serial.usart.gtpr.modify(|_, w| unsafe { w.psc().bits(prescaler) });
+                }
+
+                /// Enable or disable sending a NACK on parity errors
+                pub fn set_nack(&mut self, enable: bool) {
+                    self.serial.usart.cr3.modify(|_, w| w.nack().bit(enable));
+                }
+
+                /// Leave smartcard mode, returning the `Serial` and
+                /// the clock pin
+                pub fn free(self) -> (Serial<$USARTX, PINS>, CK) {
+                    let Smartcard { serial, ck } = self;
+                    let config = serial.config;
+                    serial.usart.cr3.modify(|_, w| w.scen().clear_bit().nack().clear_bit());
+                    serial.usart.cr2.modify(|_, w| unsafe {
+                        w.clken().clear_bit().stop().bits(config.stop_bits as u8)
+                    });
+                    serial.usart.cr1.modify(|_, w| {
+                        w.m().bit(config.m())
+                            .pce().bit(config.parity != Parity::ParityNone)
+                            .ps().bit(config.parity == Parity::ParityOdd)
+                    });
+                    (serial, ck)
+                }
+            }
+
+            impl<PINS, CK> serial::Read<u8> for Smartcard<$USARTX, PINS, CK> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    serial::Read::read(&mut Rx::<$USARTX> { _usart: PhantomData })
+                }
+            }
+
+            impl<PINS, CK> serial::Write<u8> for Smartcard<$USARTX, PINS, CK> {
+                /// `Framing` if the card answered the last byte with a NACK
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Error> {
+                    let usart = &self.serial.usart;
+                    let sr = usart.sr.read();
+                    if sr.fe().bit_is_set() {
+                        // NOTE(read_volatile) clears FE after the `SR` read
+                        unsafe { ptr::read_volatile(&usart.dr as *const _ as *const u8); }
+                        Err(nb::Error::Other(Error::Framing))
+                    } else if sr.tc().bit_is_set() {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    let usart = &self.serial.usart;
+                    if usart.sr.read().txe().bit_is_set() {
+                        // NOTE(write_volatile) 8-bit write that's not
+                        // possible through the svd2rust API
+                        unsafe {
+                            ptr::write_volatile(&usart.dr as *const _ as *mut u8, byte)
+                        }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl<PINS, CK> blocking::serial::write::Default<u8> for Smartcard<$USARTX, PINS, CK> {}
         )+
     }
 }
@@ -263,4 +1667,16 @@ hal! {
     USART1: (usart1, APB2, usart1en, usart1rst, pclk2),
     USART2: (usart2, APB1, usart2en, uart2rst, pclk1),
     USART3: (usart3, APB1, usart3en, uart3rst, pclk1),
+    UART4: (uart4, APB1, uart4en, uart4rst, pclk1),
+    UART5: (uart5, APB1, uart5en, uart5rst, pclk1),
+    USART6: (usart6, APB2, usart6en, usart6rst, pclk2),
+    UART7: (uart7, APB1, uart7en, uart7rst, pclk1),
+    UART8: (uart8, APB1, uart8en, uart8rst, pclk1),
+}
+
+usart! {
+    USART1: (usart1, APB2, usart1en, usart1rst, pclk2),
+    USART2: (usart2, APB1, usart2en, uart2rst, pclk1),
+    USART3: (usart3, APB1, usart3en, uart3rst, pclk1),
+    USART6: (usart6, APB2, usart6en, usart6rst, pclk2),
 }
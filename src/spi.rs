@@ -1,24 +1,34 @@
 //! Serial Peripheral Interface (SPI) bus
 
+use core::cell::RefCell;
+use core::marker::PhantomData;
 use core::ptr;
 
+use cortex_m::asm;
+use cortex_m::interrupt::{self, Mutex};
+use hal::blocking;
+use hal::digital::OutputPin;
 use hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use nb;
-use stm32f429::{SPI1, SPI2, SPI3, SPI5/*, SPI4, SPI6*/};
+use stm32f429::{spi1, SPI1, SPI2, SPI3, SPI4, SPI5, SPI6};
 
-use gpio::gpioa::{PA5, PA6, PA7};
-use gpio::gpiob::{PB13, PB14, PB15, PB5};
+use gpio::gpioa::{PA4, PA5, PA6, PA7};
+use gpio::gpiob::{PB9, PB12, PB13, PB14, PB15, PB5};
 use gpio::gpioc::{PC2, PC10, PC11, PC12};
 use gpio::gpiod::{PD3};
-use gpio::gpiof::{PF7, PF8, PF9};
+use gpio::gpioe::{PE2, PE4, PE5, PE6, PE11, PE12, PE13, PE14};
+use gpio::gpiof::{PF6, PF7, PF8, PF9};
+use gpio::gpiog::{PG8, PG12, PG13, PG14};
+use gpio::gpioh::{PH5};
+use gpio::gpioi::{PI0};
 use gpio::{AF5, AF6};
 use rcc::{APB1, APB2, Clocks};
 use time::Hertz;
-use dma::{DmaChannel, DmaStreamTransfer, Transfer,
-          C0, C3, dma1, dma2};
+use dma::{AbortTransfer, DmaChannel, DmaStreamTransfer, DmaStreamReceive, Fill, Transfer};
+pub use dma::{DmaRx, DmaTx, SpiDmaStream};
 
 /// SPI error
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Error {
     /// Overrun occurred
     Overrun,
@@ -26,9 +36,37 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
+    /// TI frame format error, the slave select pulse came too early
+    FrameFormat,
+    /// Underrun occurred, the master clocked a frame before one was sent
+    Underrun,
+    /// A DMA stream of the transfer failed
+    Dma,
+    /// No frame was received in time, the clock stopped too early
+    Timeout,
     #[doc(hidden)] _Extensible,
 }
 
+/// Interrupt event
+pub enum Event {
+    /// New data has been received
+    Rxne,
+    /// New data can be sent
+    Txe,
+    /// Overrun, mode fault, CRC or TI frame format error
+    Error,
+}
+
+/// Frame format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameFormat {
+    /// Motorola format, with the clock polarity and phase of the `Mode`
+    Motorola,
+    /// TI synchronous serial frame format, with a pulse on NSS before
+    /// each frame
+    Ti,
+}
+
 // FIXME these should be "closed" traits
 /// SCK pin -- DO NOT IMPLEMENT THIS TRAIT
 pub unsafe trait SckPin<SPI> {}
@@ -39,6 +77,9 @@ pub unsafe trait MisoPin<SPI> {}
 /// MOSI pin -- DO NOT IMPLEMENT THIS TRAIT
 pub unsafe trait MosiPin<SPI> {}
 
+/// NSS pin -- DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait NssPin<SPI> {}
+
 unsafe impl SckPin<SPI1> for PA5<AF5> {}
 // unsafe impl SckPin<SPI1> for PB3<AF5> {}
 
@@ -48,8 +89,13 @@ unsafe impl SckPin<SPI2> for PD3<AF5> {}
 // unsafe impl SckPin<SPI3> for PB3<AF6> {}
 unsafe impl SckPin<SPI3> for PC10<AF6> {}
 
+unsafe impl SckPin<SPI4> for PE2<AF5> {}
+unsafe impl SckPin<SPI4> for PE12<AF5> {}
+
 unsafe impl SckPin<SPI5> for PF7<AF5> {}
 
+unsafe impl SckPin<SPI6> for PG13<AF5> {}
+
 unsafe impl MisoPin<SPI1> for PA6<AF5> {}
 // unsafe impl MisoPin<SPI1> for PB4<AF5> {}
 
@@ -59,8 +105,13 @@ unsafe impl MisoPin<SPI2> for PC2<AF5> {}
 // unsafe impl MisoPin<SPI3> for PB4<AF6> {}
 unsafe impl MisoPin<SPI3> for PC11<AF6> {}
 
+unsafe impl MisoPin<SPI4> for PE5<AF5> {}
+unsafe impl MisoPin<SPI4> for PE13<AF5> {}
+
 unsafe impl MisoPin<SPI5> for PF8<AF5> {}
 
+unsafe impl MisoPin<SPI6> for PG12<AF5> {}
+
 unsafe impl MosiPin<SPI1> for PA7<AF5> {}
 unsafe impl MosiPin<SPI1> for PB5<AF5> {}
 
@@ -69,30 +120,649 @@ unsafe impl MosiPin<SPI2> for PB15<AF5> {}
 unsafe impl MosiPin<SPI3> for PB5<AF6> {}
 unsafe impl MosiPin<SPI3> for PC12<AF6> {}
 
+unsafe impl MosiPin<SPI4> for PE6<AF5> {}
+unsafe impl MosiPin<SPI4> for PE14<AF5> {}
+
 unsafe impl MosiPin<SPI5> for PF9<AF5> {}
 
-/// Rx direction
-pub struct DmaRx;
-/// Tx direction
-pub struct DmaTx;
-
-/// Possible DMA configuration for an SPI device
-pub unsafe trait SpiDmaStream<STREAM, CHANNEL, DIRECTION> {}
-unsafe impl SpiDmaStream<SPI3, C0, DmaRx> for dma1::S0 {}
-unsafe impl SpiDmaStream<SPI3, C0, DmaRx> for dma1::S2 {}
-unsafe impl SpiDmaStream<SPI2, C0, DmaRx> for dma1::S3 {}
-unsafe impl SpiDmaStream<SPI2, C0, DmaTx> for dma1::S4 {}
-unsafe impl SpiDmaStream<SPI3, C0, DmaTx> for dma1::S5 {}
-unsafe impl SpiDmaStream<SPI3, C0, DmaTx> for dma1::S7 {}
-unsafe impl SpiDmaStream<SPI1, C3, DmaRx> for dma2::S0 {}
-unsafe impl SpiDmaStream<SPI1, C3, DmaRx> for dma2::S2 {}
-unsafe impl SpiDmaStream<SPI1, C3, DmaTx> for dma2::S3 {}
-unsafe impl SpiDmaStream<SPI1, C3, DmaTx> for dma2::S5 {}
+unsafe impl MosiPin<SPI6> for PG14<AF5> {}
+
+unsafe impl NssPin<SPI1> for PA4<AF5> {}
+// unsafe impl NssPin<SPI1> for PA15<AF5> {}
+
+unsafe impl NssPin<SPI2> for PB9<AF5> {}
+unsafe impl NssPin<SPI2> for PB12<AF5> {}
+unsafe impl NssPin<SPI2> for PI0<AF5> {}
+
+unsafe impl NssPin<SPI3> for PA4<AF6> {}
+// unsafe impl NssPin<SPI3> for PA15<AF6> {}
+
+unsafe impl NssPin<SPI4> for PE4<AF5> {}
+unsafe impl NssPin<SPI4> for PE11<AF5> {}
+
+unsafe impl NssPin<SPI5> for PF6<AF5> {}
+unsafe impl NssPin<SPI5> for PH5<AF5> {}
+
+unsafe impl NssPin<SPI6> for PG8<AF5> {}
+
+/// Placeholder for an unused MISO pin
+pub struct NoMiso;
+
+/// Placeholder for an unused MOSI pin
+pub struct NoMosi;
+
+unsafe impl<SPI> MisoPin<SPI> for NoMiso {}
+
+unsafe impl<SPI> MosiPin<SPI> for NoMosi {}
+
+/// Data frame size, either `u8` or `u16`
+pub trait FrameSize {
+    #[doc(hidden)]
+    const DFF: bool;
+}
+
+impl FrameSize for u8 {
+    const DFF: bool = false;
+}
+
+impl FrameSize for u16 {
+    const DFF: bool = true;
+}
+
+/// Order in which the bits of a frame are shifted out
+pub trait BitOrder {
+    #[doc(hidden)]
+    const LSBFIRST: bool;
+}
+
+/// Most significant bit first
+pub struct MsbFirst;
+
+/// Least significant bit first
+pub struct LsbFirst;
+
+impl BitOrder for MsbFirst {
+    const LSBFIRST: bool = false;
+}
+
+impl BitOrder for LsbFirst {
+    const LSBFIRST: bool = true;
+}
 
 /// SPI peripheral operating in full duplex master mode
-pub struct Spi<SPI, PINS> {
+///
+/// `WORD` is the data frame size, either `u8` or `u16`, and `ORDER` the
+/// bit order, `MsbFirst` or `LsbFirst`.
+pub struct Spi<SPI, PINS, WORD = u8, ORDER = MsbFirst> {
+    spi: SPI,
+    pins: PINS,
+    /// Clock of the AHB bus, which the core runs at
+    hclk: Hertz,
+    /// Clock of the APB bus the peripheral is on
+    pclk: Hertz,
+    _format: PhantomData<(WORD, ORDER)>,
+}
+
+/// SPI peripheral operating in full duplex slave mode
+///
+/// The peripheral is only selected while the master drives NSS low.
+/// `WORD` and `ORDER` are the frame size and bit order, as for `Spi`.
+pub struct SpiSlave<SPI, PINS, WORD = u8, ORDER = MsbFirst> {
+    spi: SPI,
+    pins: PINS,
+    _format: PhantomData<(WORD, ORDER)>,
+}
+
+/// SPI master in bidirectional 3-wire mode
+///
+/// MOSI is the single data line, driven only while writing.
+pub struct SpiBidi<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+    hclk: Hertz,
+    pclk: Hertz,
+}
+
+/// SPI master in receive-only mode
+///
+/// The clock only runs while reading.
+pub struct SpiRxOnly<SPI, PINS> {
     spi: SPI,
     pins: PINS,
+    hclk: Hertz,
+    pclk: Hertz,
+}
+
+/// Buffer exchange in the background, driven by the SPI interrupt
+///
+/// Call `on_interrupt()` from the interrupt handler of the peripheral.
+/// One frame is in flight at a time, so a late interrupt slows the
+/// transfer down but can't cause an overrun.
+pub struct InterruptTransfer<SPI, PINS, WORD: 'static, ORDER = MsbFirst> {
+    spi: Spi<SPI, PINS, WORD, ORDER>,
+    buffer: &'static mut [WORD],
+    /// Number of frames received
+    received: usize,
+    error: Option<Error>,
+}
+
+/// Selects the smallest baud rate prescaler for which SCK doesn't
+/// exceed `freq`
+///
+/// Returns the `BR` bits and the resulting SCK frequency, at most
+/// `pclk / 2`. Below `pclk / 256`, the slowest clock the peripheral can
+/// generate, that clock is selected even though it is faster than `freq`.
+fn prescaler(pclk: u32, freq: u32) -> (u8, u32) {
+    let mut br = 0;
+    while br < 0b111 && pclk >> (br + 1) > freq {
+        br += 1;
+    }
+    (br, pclk >> (br + 1))
+}
+
+/// Full duplex DMA transfer on a pair of streams
+pub struct DuplexTransfer<TXSTREAM, RXSTREAM, TX, RX> {
+    /// Registers of the peripheral, to disable its DMA requests
+    spi: &'static spi1::RegisterBlock,
+    tx: TX,
+    rx: RX,
+    _streams: PhantomData<(TXSTREAM, RXSTREAM)>,
+}
+
+impl<TXSTREAM, RXSTREAM, TX, RX> DuplexTransfer<TXSTREAM, RXSTREAM, TX, RX>
+where
+    TX: Transfer<TXSTREAM>,
+    RX: Transfer<RXSTREAM>,
+{
+    /// Transfer is complete?
+    ///
+    /// The last frame is received after it has been sent, so this
+    /// only depends on the receiving stream.
+    pub fn is_complete(&self) -> bool {
+        self.rx.is_complete()
+    }
+
+    /// Either stream has an error?
+    pub fn has_error(&self) -> bool {
+        self.tx.has_error() || self.rx.has_error()
+    }
+
+    /// Wait until the transfer is either complete or has an error
+    ///
+    /// With CRC enabled, the CRC frame that follows the data is read and
+    /// checked. Stops both streams and disables the DMA requests of the
+    /// peripheral. Returns both streams and buffers, along with the
+    /// error on failure.
+    pub fn wait<S, B>(self) -> Result<(TXSTREAM, S, RXSTREAM, B), (Error, TXSTREAM, S, RXSTREAM, B)>
+    where
+        TX: AbortTransfer<TXSTREAM, S>,
+        RX: AbortTransfer<RXSTREAM, B>,
+    {
+        while !self.is_complete() && !self.has_error() {}
+        let result = if self.has_error() {
+            Err(Error::Dma)
+        } else if self.spi.cr1.read().crcen().bit_is_set() {
+            read_crc(self.spi)
+        } else {
+            Ok(())
+        };
+
+        let (tx_stream, tx, _) = self.tx.abort();
+        let (rx_stream, rx, _) = self.rx.abort();
+        self.spi.cr2.modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+        match result {
+            Ok(()) => Ok((tx_stream, tx, rx_stream, rx)),
+            Err(e) => Err((e, tx_stream, tx, rx_stream, rx)),
+        }
+    }
+}
+
+/// Reads the CRC frame that follows the data, then checks it
+///
+/// The frame must be read even if it is corrupt, or the next transfer
+/// would overrun.
+fn read_crc(spi: &spi1::RegisterBlock) -> Result<(), Error> {
+    loop {
+        let sr = spi.sr.read();
+        if sr.ovr().bit_is_set() {
+            return Err(Error::Overrun);
+        } else if sr.modf().bit_is_set() {
+            return Err(Error::ModeFault);
+        } else if sr.rxne().bit_is_set() {
+            break;
+        }
+    }
+    spi.dr.read();
+
+    if spi.sr.read().crcerr().bit_is_set() {
+        spi.sr.modify(|_, w| w.crcerr().clear_bit());
+        Err(Error::Crc)
+    } else {
+        Ok(())
+    }
+}
+
+/// Receives `words` while the master clocks freely, stopping the clock
+/// after the last frame with the receive-only procedure of RM0090:
+/// the peripheral is disabled one SCK period after the second to last
+/// frame is received, or after the clock starts for a single frame
+///
+/// The clock must already be running unless `words` is empty.
+fn receive(
+    spi: &spi1::RegisterBlock,
+    words: &mut [u8],
+    hclk: Hertz,
+    pclk: Hertz,
+) -> Result<(), Error> {
+    // SCK period in core clock cycles
+    let br = spi.cr1.read().br().bits();
+    let period = (hclk.0 / pclk.0) << (br + 1);
+
+    let n = words.len();
+    if n == 1 {
+        asm::delay(period);
+        spi.cr1.modify(|_, w| w.spe().clear_bit());
+    }
+
+    for (i, word) in words.iter_mut().enumerate() {
+        // a frame takes 8 SCK periods, a poll at least one core cycle
+        let mut timeout = 16 * period;
+        loop {
+            let sr = spi.sr.read();
+            if sr.ovr().bit_is_set() {
+                spi.cr1.modify(|_, w| w.spe().clear_bit());
+                // NOTE(read_volatile) reading DR, then SR clears OVR
+                unsafe {
+                    ptr::read_volatile(&spi.dr as *const _ as *const u8);
+                }
+                spi.sr.read();
+                return Err(Error::Overrun);
+            } else if sr.rxne().bit_is_set() {
+                break;
+            } else if timeout == 0 {
+                spi.cr1.modify(|_, w| w.spe().clear_bit());
+                return Err(Error::Timeout);
+            }
+            timeout -= 1;
+        }
+
+        if i + 2 == n {
+            // the last frame has started
+            asm::delay(period);
+            spi.cr1.modify(|_, w| w.spe().clear_bit());
+        }
+
+        // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
+        // reading a half-word)
+        *word = unsafe { ptr::read_volatile(&spi.dr as *const _ as *const u8) };
+    }
+    Ok(())
+}
+
+/// SPI master whose clock settings can be changed between transactions
+pub trait Reconfigure {
+    /// Changes the clock polarity and phase
+    fn set_mode(&mut self, mode: Mode);
+    /// Changes the SCK frequency to the highest one that doesn't
+    /// exceed `freq`, returning it
+    fn set_frequency(&mut self, freq: Hertz) -> Hertz;
+}
+
+/// SPI bus shared by several devices with their own chip select
+pub struct SharedBus<SPI> {
+    bus: Mutex<RefCell<Bus<SPI>>>,
+}
+
+/// SPI master with the settings of the last transaction
+struct Bus<SPI> {
+    spi: SPI,
+    /// Mode and requested SCK frequency, `None` before the first
+    /// transaction
+    settings: Option<(Mode, u32)>,
+}
+
+impl<SPI: Reconfigure> SharedBus<SPI> {
+    /// Takes ownership of the SPI master
+    pub fn new(spi: SPI) -> Self {
+        SharedBus { bus: Mutex::new(RefCell::new(Bus { spi, settings: None })) }
+    }
+
+    /// Returns a handle to the device selected by `cs`, which is
+    /// driven high until a transaction
+    pub fn device<CS, F>(&self, mut cs: CS, mode: Mode, freq: F) -> SpiDevice<'_, SPI, CS>
+    where
+        CS: OutputPin,
+        F: Into<Hertz>,
+    {
+        cs.set_high();
+        SpiDevice { bus: &self.bus, cs, mode, freq: freq.into() }
+    }
+}
+
+/// Device on a `SharedBus`
+pub struct SpiDevice<'a, SPI: 'a, CS> {
+    bus: &'a Mutex<RefCell<Bus<SPI>>>,
+    cs: CS,
+    mode: Mode,
+    freq: Hertz,
+}
+
+impl<'a, SPI: Reconfigure, CS: OutputPin> SpiDevice<'a, SPI, CS> {
+    /// Runs `f` with the bus configured for this device and the device
+    /// selected
+    ///
+    /// Interrupts are disabled for the duration of the transaction. The
+    /// bus is only reconfigured if the previous transaction was made with
+    /// other settings, so `f` must not change the mode or the frequency.
+    ///
+    /// Panics if called from `f`, as the bus is already in use.
+    pub fn transaction<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut SPI) -> R,
+    {
+        let (bus, mode, freq) = (self.bus, self.mode, self.freq);
+        let cs = &mut self.cs;
+        interrupt::free(|token| {
+            let mut bus = bus.borrow(token).borrow_mut();
+            let settings = Some((mode, freq.0));
+            if bus.settings != settings {
+                bus.spi.set_mode(mode);
+                bus.spi.set_frequency(freq);
+                bus.settings = settings;
+            }
+
+            cs.set_low();
+            let result = f(&mut bus.spi);
+            cs.set_high();
+            result
+        })
+    }
+
+    /// Releases the chip select pin
+    pub fn free(self) -> CS {
+        self.cs
+    }
+}
+
+/// Implements the blocking traits of `SpiDevice` for each frame size
+macro_rules! device_words {
+    ($($word:ty),+) => {
+        $(
+            impl<'a, SPI, CS> blocking::spi::Transfer<$word> for SpiDevice<'a, SPI, CS>
+            where
+                SPI: Reconfigure + blocking::spi::Transfer<$word>,
+                CS: OutputPin,
+            {
+                type Error = SPI::Error;
+
+                fn transfer<'w>(&mut self, words: &'w mut [$word]) -> Result<&'w [$word], SPI::Error> {
+                    self.transaction(move |spi| spi.transfer(words))
+                }
+            }
+
+            impl<'a, SPI, CS> blocking::spi::Write<$word> for SpiDevice<'a, SPI, CS>
+            where
+                SPI: Reconfigure + blocking::spi::Write<$word>,
+                CS: OutputPin,
+            {
+                type Error = SPI::Error;
+
+                fn write(&mut self, words: &[$word]) -> Result<(), SPI::Error> {
+                    self.transaction(|spi| spi.write(words))
+                }
+            }
+        )+
+    }
+}
+
+device_words!(u8, u16);
+
+/// Implements the traits that depend on the data frame size
+macro_rules! words {
+    ($SPIX:ident: $($word:ty),+) => {
+        $(
+            impl<PINS, ORDER> Spi<$SPIX, PINS, $word, ORDER> {
+                /// Start receiving into `rx` with DMA, sending a dummy
+                /// value of all ones for each frame
+                pub fn dma_read<TXSTREAM, RXSTREAM, TXCHANNEL, RXCHANNEL, TX, RX>(
+                    &mut self,
+                    tx_stream: TXSTREAM,
+                    rx_stream: RXSTREAM,
+                    rx: &'static mut [$word],
+                ) -> DuplexTransfer<TXSTREAM, RXSTREAM, TX, RX>
+                where
+                    TXSTREAM: DmaStreamTransfer<Fill<$word>, $word, TX> + SpiDmaStream<$SPIX, TXCHANNEL, DmaTx>,
+                    RXSTREAM: DmaStreamReceive<$word, &'static mut [$word], RX> + SpiDmaStream<$SPIX, RXCHANNEL, DmaRx>,
+                    TXCHANNEL: DmaChannel,
+                    RXCHANNEL: DmaChannel,
+                    TX: Transfer<TXSTREAM>,
+                    RX: Transfer<RXSTREAM>,
+                {
+                    static DUMMY: $word = !0;
+
+                    let tx = Fill::new(&DUMMY, rx.len());
+                    self.start_duplex::<_, TXCHANNEL, RXCHANNEL, _, _, _, _>(tx_stream, rx_stream, tx, rx)
+                }
+
+                fn send_blocking(&mut self, word: $word) -> Result<(), Error> {
+                    loop {
+                        match self.send(word) {
+                            Ok(()) => return Ok(()),
+                            Err(nb::Error::Other(e)) => return Err(e),
+                            Err(nb::Error::WouldBlock) => {}
+                        }
+                    }
+                }
+
+                fn read_blocking(&mut self) -> Result<$word, Error> {
+                    loop {
+                        match self.read() {
+                            Ok(word) => return Ok(word),
+                            Err(nb::Error::Other(e)) => return Err(e),
+                            Err(nb::Error::WouldBlock) => {}
+                        }
+                    }
+                }
+            }
+
+            impl<PINS, ORDER> FullDuplex<$word> for Spi<$SPIX, PINS, $word, ORDER> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<$word, Error> {
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.tifrfe().bit_is_set() {
+                        // reading SR clears FRE
+                        nb::Error::Other(Error::FrameFormat)
+                    } else if sr.crcerr().bit_is_set() {
+                        self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) read only the frame size (the svd2rust API only
+                        // allows reading a half-word)
+                        return Ok(unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const $word)
+                        });
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+
+                fn send(&mut self, byte: $word) -> nb::Result<(), Error> {
+                    let sr = self.spi.sr.read();
+
+                    Err(/*if sr.ovr().bit_is_set() {
+                        // Clear the flag:
+                        unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const $word)
+                        };
+                        nb::Error::Other(Error::Overrun)
+                    } else*/ if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.crcerr().bit_is_set() {
+                        self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.txe().bit_is_set() {
+                        // NOTE(write_volatile) see note above
+                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut $word, byte) }
+                        return Ok(());
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+            }
+
+            impl<PINS, ORDER> ::hal::blocking::spi::Transfer<$word> for Spi<$SPIX, PINS, $word, ORDER> {
+                type Error = Error;
+
+                fn transfer<'w>(&mut self, words: &'w mut [$word]) -> Result<&'w [$word], Error> {
+                    let crc = self.spi.cr1.read().crcen().bit_is_set();
+                    let n = words.len();
+
+                    // keep the next frame in DR while the previous one is shifted, and read each
+                    // frame before the following one completes so that OVR can't occur
+                    for i in 0..n + 1 {
+                        if i < n {
+                            self.send_blocking(words[i])?;
+                            // the CRC follows the last frame
+                            if crc && i + 1 == n {
+                                self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+                            }
+                        }
+                        if i > 0 {
+                            words[i - 1] = self.read_blocking()?;
+                        }
+                    }
+
+                    if crc && n > 0 {
+                        read_crc(&self.spi)?;
+                    }
+
+                    Ok(words)
+                }
+            }
+
+            impl<PINS, ORDER> ::hal::blocking::spi::Write<$word> for Spi<$SPIX, PINS, $word, ORDER> {
+                type Error = Error;
+
+                fn write(&mut self, words: &[$word]) -> Result<(), Error> {
+                    let crc = self.spi.cr1.read().crcen().bit_is_set();
+                    for (i, word) in words.iter().enumerate() {
+                        loop {
+                            let sr = self.spi.sr.read();
+
+                            // ignore overruns because we don't care about the incoming data
+                            if sr.modf().bit_is_set() {
+                                return Err(Error::ModeFault);
+                            } else if sr.txe().bit_is_set() {
+                                break;
+                            }
+                        }
+
+                        // NOTE(write_volatile) see note above
+                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut $word, *word) }
+                        // the CRC follows the last frame
+                        if crc && i + 1 == words.len() {
+                            self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+                        }
+                    }
+
+                    // wait until the last frame has moved to the shift register, then until it
+                    // has been shifted out; BSY alone may be low in between
+                    while self.spi.sr.read().txe().bit_is_clear() {}
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    // clear OVR flag
+                    unsafe {
+                        ptr::read_volatile(&self.spi.dr as *const _ as *const $word);
+                    }
+                    self.spi.sr.read();
+
+                    // the received data, and thus its CRC, is ignored
+                    if crc {
+                        self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                    }
+
+                    Ok(())
+                }
+            }
+
+            impl<PINS, ORDER> SpiSlave<$SPIX, PINS, $word, ORDER> {
+                /// Start receiving exactly `buffer.len()` frames from the
+                /// master with DMA
+                pub fn dma_read<STREAM, CHANNEL, X>(&mut self, dma: STREAM, buffer: &'static mut [$word]) -> X
+                where
+                    STREAM: DmaStreamReceive<$word, &'static mut [$word], X> + SpiDmaStream<$SPIX, CHANNEL, DmaRx>,
+                    CHANNEL: DmaChannel,
+                    X: Transfer<STREAM>,
+                {
+                    self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+
+                    // NOTE(unsafe) the DMA reads only the frame size
+                    let dr: &$word = unsafe {
+                        &*(&self.spi.dr as *const _ as *const $word)
+                    };
+                    dma.start_receive::<CHANNEL>(dr, buffer)
+                }
+            }
+
+            impl<PINS, ORDER> FullDuplex<$word> for SpiSlave<$SPIX, PINS, $word, ORDER> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<$word, Error> {
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        // NOTE(read_volatile) reading DR, then SR clears OVR
+                        unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const $word);
+                        }
+                        self.spi.sr.read();
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.crcerr().bit_is_set() {
+                        self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) read only the frame size (the svd2rust API only
+                        // allows reading a half-word)
+                        return Ok(unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const $word)
+                        });
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+
+                fn send(&mut self, byte: $word) -> nb::Result<(), Error> {
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        // NOTE(read_volatile) reading DR, then SR clears OVR
+                        unsafe {
+                            ptr::read_volatile(&self.spi.dr as *const _ as *const $word);
+                        }
+                        self.spi.sr.read();
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.udr().bit_is_set() {
+                        // reading SR clears UDR
+                        nb::Error::Other(Error::Underrun)
+                    } else if sr.txe().bit_is_set() {
+                        // NOTE(write_volatile) see note above
+                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut $word, byte) }
+                        return Ok(());
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+            }
+        )+
+    }
 }
 
 macro_rules! hal {
@@ -100,6 +770,9 @@ macro_rules! hal {
         $(
             impl<SCK, MISO, MOSI> Spi<$SPIX, (SCK, MISO, MOSI)> {
                 /// Configures the SPI peripheral to operate in full duplex master mode
+                ///
+                /// Frames are 8 bits, MSB first; see `into_format()`.
+                /// Frequencies below `pclk / 256` are raised to it.
                 pub fn $spiX<F>(
                     spi: $SPIX,
                     pins: (SCK, MISO, MOSI),
@@ -126,17 +799,8 @@ macro_rules! hal {
                                   .ssoe().set_bit()
                     );
 
-                    let br = match clocks.$pclkX().0 / freq.into().0 {
-                        0 => unreachable!(),
-                        1...2 => 0b000,
-                        3...5 => 0b001,
-                        6...11 => 0b010,
-                        12...23 => 0b011,
-                        24...39 => 0b100,
-                        40...95 => 0b101,
-                        96...191 => 0b110,
-                        _ => 0b111,
-                    };
+                    let pclk = clocks.$pclkX();
+                    let (br, _) = prescaler(pclk.0, freq.into().0);
 
                     spi.cr1.write(|w| unsafe {
                         w
@@ -148,7 +812,7 @@ macro_rules! hal {
                             .cpol().bit(mode.polarity == Polarity::IdleHigh)
                             // Master mode
                             .mstr().set_bit()
-                            // 1 MHz
+                            // Baud rate
                             .br().bits(br)
                             // Enable SPI
                             .spe().set_bit()
@@ -166,7 +830,140 @@ macro_rules! hal {
                             .rxonly().clear_bit()
                     });
 
-                    Spi { spi, pins }
+                    Spi { spi, pins, hclk: clocks.hclk(), pclk, _format: PhantomData }
+                }
+            }
+
+            impl<PINS, WORD, ORDER> Spi<$SPIX, PINS, WORD, ORDER> {
+                /// Switch to `NEWWORD` frames shifted out in `NEWORDER`,
+                /// e.g. `spi.into_format::<u16, LsbFirst>()`
+                pub fn into_format<NEWWORD, NEWORDER>(mut self) -> Spi<$SPIX, PINS, NEWWORD, NEWORDER>
+                where
+                    NEWWORD: FrameSize,
+                    NEWORDER: BitOrder,
+                {
+                    self.reconfigure(|w| w.dff().bit(NEWWORD::DFF).lsbfirst().bit(NEWORDER::LSBFIRST));
+                    Spi { spi: self.spi, pins: self.pins, hclk: self.hclk, pclk: self.pclk, _format: PhantomData }
+                }
+
+                /// Returns the actual SCK frequency
+                pub fn frequency(&self) -> Hertz {
+                    let br = self.spi.cr1.read().br().bits();
+                    Hertz(self.pclk.0 >> (br + 1))
+                }
+
+                /// Changes the SCK frequency to the highest one that
+                /// doesn't exceed `freq`, returning it
+                ///
+                /// Below `pclk / 256`, that frequency is returned instead.
+                pub fn set_frequency<F: Into<Hertz>>(&mut self, freq: F) -> Hertz {
+                    let (br, actual) = prescaler(self.pclk.0, freq.into().0);
+                    self.reconfigure(|w| unsafe { w.br().bits(br) });
+                    Hertz(actual)
+                }
+
+                /// Changes the clock polarity and phase
+                pub fn set_mode(&mut self, mode: Mode) {
+                    self.reconfigure(|w| {
+                        w.cpha().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol().bit(mode.polarity == Polarity::IdleHigh)
+                    });
+                }
+
+                /// Changes `CR1` with the peripheral disabled, as
+                /// required for the frame format bits
+                fn reconfigure<F>(&mut self, f: F)
+                where
+                    F: FnOnce(&mut spi1::cr1::W) -> &mut spi1::cr1::W,
+                {
+                    // wait until the transmission of the last frame is done
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| f(w));
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                }
+
+                /// Enables the hardware CRC unit with the given polynomial
+                ///
+                /// The CRC is sent after the last frame of a DMA transfer
+                /// or of a blocking write. The checksum of received data
+                /// is compared with the CRC frame that follows it.
+                pub fn enable_crc(&mut self, polynomial: u16) {
+                    self.spi.crcpr.write(|w| unsafe { w.bits(u32::from(polynomial)) });
+                    self.reset_crc();
+                }
+
+                /// Disables the hardware CRC unit
+                pub fn disable_crc(&mut self) {
+                    self.reconfigure(|w| w.crcen().clear_bit());
+                }
+
+                /// Resets the CRC of both directions before a new transaction
+                pub fn reset_crc(&mut self) {
+                    self.reconfigure(|w| w.crcen().clear_bit());
+                    self.reconfigure(|w| w.crcen().set_bit());
+                }
+
+                /// Sends the CRC after the frame that was last written
+                ///
+                /// Only needed with `FullDuplex::send()`.
+                pub fn send_crc(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+                }
+
+                /// Returns the CRC computed over the transmitted frames
+                pub fn tx_crc(&self) -> u16 {
+                    self.spi.txcrcr.read().bits() as u16
+                }
+
+                /// Returns the CRC computed over the received frames
+                pub fn rx_crc(&self) -> u16 {
+                    self.spi.rxcrcr.read().bits() as u16
+                }
+
+                /// Checks the received CRC frame, clearing the error
+                ///
+                /// Call this after the CRC frame has been read from the
+                /// data register.
+                pub fn check_crc(&mut self) -> Result<(), Error> {
+                    if self.spi.sr.read().crcerr().bit_is_set() {
+                        self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                        Err(Error::Crc)
+                    } else {
+                        Ok(())
+                    }
+                }
+
+                /// Selects the Motorola or TI frame format
+                ///
+                /// In TI mode, NSS is driven by the hardware and the
+                /// clock polarity and phase are ignored.
+                pub fn set_frame_format(&mut self, format: FrameFormat) {
+                    // wait until the transmission of the last frame is done
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr2.modify(|_, w| w.frf().bit(format == FrameFormat::Ti));
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::Rxne => self.spi.cr2.modify(|_, w| w.rxneie().set_bit()),
+                        Event::Txe => self.spi.cr2.modify(|_, w| w.txeie().set_bit()),
+                        Event::Error => self.spi.cr2.modify(|_, w| w.errie().set_bit()),
+                    }
+                }
+
+                /// Stops listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::Rxne => self.spi.cr2.modify(|_, w| w.rxneie().clear_bit()),
+                        Event::Txe => self.spi.cr2.modify(|_, w| w.txeie().clear_bit()),
+                        Event::Error => self.spi.cr2.modify(|_, w| w.errie().clear_bit()),
+                    }
                 }
 
                 /// Enable transmit interrupt
@@ -180,11 +977,14 @@ macro_rules! hal {
                 }
 
                 /// Releases the SPI peripheral and associated pins
-                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI)) {
+                pub fn free(self) -> ($SPIX, PINS) {
                     (self.spi, self.pins)
                 }
 
                 /// Start a one-shot DMA transfer
+                ///
+                /// With the CRC unit enabled, the CRC is sent after the
+                /// last frame.
                 pub fn dma_write<S, T, STREAM, CHANNEL, X>(&mut self, dma: STREAM, data: S) -> X
                 where
                     S: AsRef<[T]>,
@@ -193,101 +993,379 @@ macro_rules! hal {
                     X: Transfer<STREAM>,
                 {
                     self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
-                    
-                    let dr: &mut T = unsafe {
-                        &mut *(&self.spi.dr as *const _ as *mut T)
-                    };
+
+                    let dr = &self.spi.dr as *const _ as *mut T;
                     dma.start_transfer::<CHANNEL>(data, dr)
                 }
+
+                /// Start a full duplex DMA transfer, receiving into
+                /// `rx` while sending `tx`
+                ///
+                /// Both buffers must have the same length.
+                pub fn dma_transfer<S, TXSTREAM, RXSTREAM, TXCHANNEL, RXCHANNEL, TX, RX>(
+                    &mut self,
+                    tx_stream: TXSTREAM,
+                    rx_stream: RXSTREAM,
+                    tx: S,
+                    rx: &'static mut [WORD],
+                ) -> DuplexTransfer<TXSTREAM, RXSTREAM, TX, RX>
+                where
+                    S: AsRef<[WORD]>,
+                    TXSTREAM: DmaStreamTransfer<S, WORD, TX> + SpiDmaStream<$SPIX, TXCHANNEL, DmaTx>,
+                    RXSTREAM: DmaStreamReceive<WORD, &'static mut [WORD], RX> + SpiDmaStream<$SPIX, RXCHANNEL, DmaRx>,
+                    TXCHANNEL: DmaChannel,
+                    RXCHANNEL: DmaChannel,
+                    TX: Transfer<TXSTREAM>,
+                    RX: Transfer<RXSTREAM>,
+                {
+                    assert_eq!(tx.as_ref().len(), rx.len());
+                    self.start_duplex::<S, TXCHANNEL, RXCHANNEL, _, _, _, _>(tx_stream, rx_stream, tx, rx)
+                }
+
+                /// Starts the receiving stream before the transmitting
+                /// one, so that no frame is lost
+                fn start_duplex<S, TXCHANNEL, RXCHANNEL, TXSTREAM, RXSTREAM, TX, RX>(
+                    &mut self,
+                    tx_stream: TXSTREAM,
+                    rx_stream: RXSTREAM,
+                    tx: S,
+                    rx: &'static mut [WORD],
+                ) -> DuplexTransfer<TXSTREAM, RXSTREAM, TX, RX>
+                where
+                    TXSTREAM: DmaStreamTransfer<S, WORD, TX>,
+                    RXSTREAM: DmaStreamReceive<WORD, &'static mut [WORD], RX>,
+                    TXCHANNEL: DmaChannel,
+                    RXCHANNEL: DmaChannel,
+                    TX: Transfer<TXSTREAM>,
+                    RX: Transfer<RXSTREAM>,
+                {
+                    // clear a stale overrun by reading DR, then SR
+                    unsafe {
+                        ptr::read_volatile(&self.spi.dr as *const _ as *const WORD);
+                    }
+                    self.spi.sr.read();
+
+                    self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+                    let dr = &self.spi.dr as *const _ as *const WORD;
+                    // NOTE(unsafe) the streams only access the data register
+                    let rx = rx_stream.start_receive::<RXCHANNEL>(unsafe { &*dr }, rx);
+                    let tx = tx_stream.start_transfer::<TXCHANNEL>(tx, dr as *mut WORD);
+                    self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+                    // NOTE(unsafe) the transfer only writes the DMA enable bits of `CR2`
+                    let spi = unsafe { &*$SPIX::ptr() };
+                    DuplexTransfer { spi, tx, rx, _streams: PhantomData }
+                }
             }
 
-            impl<PINS> FullDuplex<u8> for Spi<$SPIX, PINS> {
-                type Error = Error;
+            impl<PINS, WORD: Copy, ORDER> Spi<$SPIX, PINS, WORD, ORDER> {
+                /// Start exchanging the contents of `buffer` in the
+                /// background
+                ///
+                /// Each frame received replaces the one sent.
+                pub fn transfer_interrupt(self, buffer: &'static mut [WORD]) -> InterruptTransfer<$SPIX, PINS, WORD, ORDER> {
+                    let mut transfer = InterruptTransfer {
+                        spi: self,
+                        buffer,
+                        received: 0,
+                        error: None,
+                    };
+                    if !transfer.buffer.is_empty() {
+                        transfer.send_next();
+                        transfer.spi.spi.cr2.modify(|_, w| w.rxneie().set_bit().errie().set_bit());
+                    }
+                    transfer
+                }
+            }
 
-                fn read(&mut self) -> nb::Result<u8, Error> {
-                    let sr = self.spi.sr.read();
+            impl<PINS, WORD: Copy, ORDER> InterruptTransfer<$SPIX, PINS, WORD, ORDER> {
+                /// Advances the transfer, to be called from the
+                /// interrupt handler
+                pub fn on_interrupt(&mut self) {
+                    if self.error.is_some() || self.received == self.buffer.len() {
+                        return;
+                    }
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
+                    let sr = self.spi.spi.sr.read();
+                    let error = if sr.ovr().bit_is_set() {
+                        // NOTE(read_volatile) reading DR, then SR clears OVR
+                        unsafe {
+                            ptr::read_volatile(&self.spi.spi.dr as *const _ as *const WORD);
+                        }
+                        self.spi.spi.sr.read();
+                        Some(Error::Overrun)
                     } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
+                        Some(Error::ModeFault)
+                    } else if sr.tifrfe().bit_is_set() {
+                        Some(Error::FrameFormat)
                     } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.rxne().bit_is_set() {
-                        // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
-                        // reading a half-word)
-                        return Ok(unsafe {
-                            ptr::read_volatile(&self.spi.dr as *const _ as *const u8)
-                        });
+                        self.spi.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                        Some(Error::Crc)
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        None
+                    };
+                    if error.is_some() {
+                        self.error = error;
+                        self.stop();
+                        return;
+                    }
+
+                    if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) read only the frame size
+                        self.buffer[self.received] = unsafe {
+                            ptr::read_volatile(&self.spi.spi.dr as *const _ as *const WORD)
+                        };
+                        self.received += 1;
+
+                        if self.received < self.buffer.len() {
+                            self.send_next();
+                        } else {
+                            self.stop();
+                        }
+                    }
                 }
 
-                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
-                    let sr = self.spi.sr.read();
+                /// Returns `Ok` once the transfer has completed
+                pub fn result(&self) -> nb::Result<(), Error> {
+                    match self.error {
+                        Some(error) => Err(nb::Error::Other(error)),
+                        None if self.received == self.buffer.len() => Ok(()),
+                        None => Err(nb::Error::WouldBlock),
+                    }
+                }
 
-                    Err(/*if sr.ovr().bit_is_set() {
-                        // Clear the flag:
-                        unsafe {
-                            ptr::read_volatile(&self.spi.dr as *const _ as *const u8)
-                        };
-                        nb::Error::Other(Error::Overrun)
-                    } else*/ if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.txe().bit_is_set() {
-                        // NOTE(write_volatile) see note above
-                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, byte) }
-                        return Ok(());
-                    } else {
-                        nb::Error::WouldBlock
-                    })
+                /// Number of frames that have been exchanged
+                pub fn transferred(&self) -> usize {
+                    self.received
+                }
+
+                /// Stops the transfer, returning the `Spi` and the buffer
+                pub fn free(mut self) -> (Spi<$SPIX, PINS, WORD, ORDER>, &'static mut [WORD]) {
+                    self.stop();
+                    (self.spi, self.buffer)
+                }
+
+                fn send_next(&mut self) {
+                    // NOTE(write_volatile) TXE is set as the previous
+                    // frame has been received
+                    unsafe {
+                        ptr::write_volatile(&self.spi.spi.dr as *const _ as *mut WORD, self.buffer[self.received])
+                    }
+                }
+
+                fn stop(&mut self) {
+                    self.spi.spi.cr2.modify(|_, w| w.rxneie().clear_bit().errie().clear_bit());
                 }
             }
 
-            impl<PINS> ::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
-            // impl<PINS> ::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
+            impl<PINS, WORD, ORDER> Reconfigure for Spi<$SPIX, PINS, WORD, ORDER> {
+                fn set_mode(&mut self, mode: Mode) {
+                    <Spi<$SPIX, PINS, WORD, ORDER>>::set_mode(self, mode)
+                }
+
+                fn set_frequency(&mut self, freq: Hertz) -> Hertz {
+                    <Spi<$SPIX, PINS, WORD, ORDER>>::set_frequency(self, freq)
+                }
+            }
+
+            impl<SCK, MOSI> Spi<$SPIX, (SCK, NoMiso, MOSI)> {
+                /// Switch to bidirectional 3-wire mode, using MOSI as
+                /// the data line
+                pub fn into_bidi(mut self) -> SpiBidi<$SPIX, (SCK, NoMiso, MOSI)> {
+                    self.reconfigure(|w| w.bidimode().set_bit().bidioe().set_bit());
+                    SpiBidi { spi: self.spi, pins: self.pins, hclk: self.hclk, pclk: self.pclk }
+                }
+            }
+
+            impl<PINS> SpiBidi<$SPIX, PINS> {
+                /// Receives `words` over the data line
+                pub fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    if words.is_empty() {
+                        return Ok(());
+                    }
+
+                    // turning the output off starts the clock
+                    self.spi.cr1.modify(|_, w| w.bidioe().clear_bit());
+                    let result = receive(&self.spi, words, self.hclk, self.pclk);
+
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    result
+                }
+
+                /// Switch back to full duplex mode
+                pub fn into_full_duplex(self) -> Spi<$SPIX, PINS> {
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.bidimode().clear_bit().bidioe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    Spi { spi: self.spi, pins: self.pins, hclk: self.hclk, pclk: self.pclk, _format: PhantomData }
+                }
 
-            impl<PINS> ::hal::blocking::spi::Write<u8> for Spi<$SPIX, PINS> {
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, PINS) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<PINS> ::hal::blocking::spi::Write<u8> for SpiBidi<$SPIX, PINS> {
                 type Error = Error;
 
                 fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
                     for byte in bytes {
-                        'l: loop {
+                        loop {
                             let sr = self.spi.sr.read();
-
-                            // ignore overruns because we don't care about the incoming data
-                            // if sr.ovr().bit_is_set() {
-                            // Err(nb::Error::Other(Error::Overrun))
-                            // } else
                             if sr.modf().bit_is_set() {
                                 return Err(Error::ModeFault);
-                            } else if sr.crcerr().bit_is_set() {
-                                return Err(Error::Crc);
                             } else if sr.txe().bit_is_set() {
-                                // NOTE(write_volatile) see note above
-                                unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, *byte) }
-                                break 'l;
-                            } else {
-                                // try again
+                                break;
                             }
                         }
+                        // NOTE(write_volatile) see note above
+                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, *byte) }
                     }
 
                     // wait until the transmission of the last byte is done
+                    while self.spi.sr.read().txe().bit_is_clear() {}
                     while self.spi.sr.read().bsy().bit_is_set() {}
 
-                    // clear OVR flag
-                    unsafe {
-                        ptr::read_volatile(&self.spi.dr as *const _ as *const u8);
+                    Ok(())
+                }
+            }
+
+            impl<SCK, MISO> Spi<$SPIX, (SCK, MISO, NoMosi)> {
+                /// Switch to receive-only mode
+                pub fn into_rx_only(self) -> SpiRxOnly<$SPIX, (SCK, MISO, NoMosi)> {
+                    // wait until the transmission of the last frame is done
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    // the clock starts as soon as the peripheral is enabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.rxonly().set_bit());
+                    SpiRxOnly { spi: self.spi, pins: self.pins, hclk: self.hclk, pclk: self.pclk }
+                }
+            }
+
+            impl<PINS> SpiRxOnly<$SPIX, PINS> {
+                /// Receives `words`
+                pub fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    if words.is_empty() {
+                        return Ok(());
                     }
-                    self.spi.sr.read();
 
-                    Ok(())
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    let result = receive(&self.spi, words, self.hclk, self.pclk);
+
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    result
+                }
+
+                /// Switch back to full duplex mode
+                pub fn into_full_duplex(self) -> Spi<$SPIX, PINS> {
+                    self.spi.cr1.modify(|_, w| w.rxonly().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    Spi { spi: self.spi, pins: self.pins, hclk: self.hclk, pclk: self.pclk, _format: PhantomData }
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, PINS) {
+                    (self.spi, self.pins)
                 }
             }
+
+            impl<SCK, MISO, MOSI, NSS> SpiSlave<$SPIX, (SCK, MISO, MOSI, NSS)> {
+                /// Configures the SPI peripheral to operate in full duplex slave mode
+                ///
+                /// Frames are 8 bits, MSB first; see `into_format()`.
+                pub fn $spiX(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, NSS),
+                    mode: Mode,
+                    apb: &mut $APBX,
+                ) -> Self
+                where
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    // enable or reset $SPIX
+                    apb.enr().modify(|_, w| w.$spiXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+
+                    spi.cr1.write(|w| {
+                        w
+                            // 8-bit data frame format
+                            .dff().clear_bit()
+                            // Clock phase
+                            .cpha().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            // Clock polariy
+                            .cpol().bit(mode.polarity == Polarity::IdleHigh)
+                            // Slave mode
+                            .mstr().clear_bit()
+                            // Enable SPI
+                            .spe().set_bit()
+                            // MSB transmitted first
+                            .lsbfirst().clear_bit()
+                            // Hardware slave management through the NSS pin
+                            .ssm().clear_bit()
+                            // Disable CRC calculation
+                            .crcen().clear_bit()
+                            // 2-line unidirectional data mode
+                            .bidimode().clear_bit()
+                            // Full duplex
+                            .rxonly().clear_bit()
+                    });
+
+                    SpiSlave { spi, pins, _format: PhantomData }
+                }
+            }
+
+            impl<PINS, WORD, ORDER> SpiSlave<$SPIX, PINS, WORD, ORDER> {
+                /// Switch to `NEWWORD` frames shifted in and out in
+                /// `NEWORDER`, matching the master
+                pub fn into_format<NEWWORD, NEWORDER>(self) -> SpiSlave<$SPIX, PINS, NEWWORD, NEWORDER>
+                where
+                    NEWWORD: FrameSize,
+                    NEWORDER: BitOrder,
+                {
+                    // wait until the master has finished the current frame
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.dff().bit(NEWWORD::DFF).lsbfirst().bit(NEWORDER::LSBFIRST));
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    SpiSlave { spi: self.spi, pins: self.pins, _format: PhantomData }
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, PINS) {
+                    (self.spi, self.pins)
+                }
+
+                /// Start a one-shot DMA transfer of `data` to the master
+                pub fn dma_write<S, T, STREAM, CHANNEL, X>(&mut self, dma: STREAM, data: S) -> X
+                where
+                    S: AsRef<[T]>,
+                    STREAM: DmaStreamTransfer<S, T, X> + SpiDmaStream<$SPIX, CHANNEL, DmaTx>,
+                    CHANNEL: DmaChannel,
+                    X: Transfer<STREAM>,
+                {
+                    self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+                    let dr = &self.spi.dr as *const _ as *mut T;
+                    dma.start_transfer::<CHANNEL>(data, dr)
+                }
+
+                /// Returns `true` while the master selects this slave
+                /// and a frame is being shifted
+                pub fn is_busy(&self) -> bool {
+                    self.spi.sr.read().bsy().bit_is_set()
+                }
+            }
+
+            words!($SPIX: u8, u16);
         )+
     }
 }
@@ -296,50 +1374,43 @@ hal! {
     SPI1: (spi1, APB2, spi1en, spi1rst, pclk2),
     SPI2: (spi2, APB1, spi2en, spi2rst, pclk1),
     SPI3: (spi3, APB1, spi3en, spi3rst, pclk1),
-    SPI5: (spi5, APB2, spi5en, spi5rst, pclk2),
-    /* Available in the datasheet but not in the .svd:
     SPI4: (spi4, APB2, spi4en, spi4rst, pclk2),
+    SPI5: (spi5, APB2, spi5en, spi5rst, pclk2),
     SPI6: (spi6, APB2, spi6en, spi6rst, pclk2),
-     */
 }
 
-// FIXME not working
-// TODO measure if this actually faster than the default implementation
-// impl ::hal::blocking::spi::Write<u8> for Spi {
-//     type Error = Error;
-
-//     fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
-//         for byte in bytes {
-//             'l: loop {
-//                 let sr = self.spi.sr.read();
-
-//                 // ignore overruns because we don't care about the incoming data
-//                 // if sr.ovr().bit_is_set() {
-//                 // Err(nb::Error::Other(Error::Overrun))
-//                 // } else
-//                 if sr.modf().bit_is_set() {
-//                     return Err(Error::ModeFault);
-//                 } else if sr.crcerr().bit_is_set() {
-//                     return Err(Error::Crc);
-//                 } else if sr.txe().bit_is_set() {
-//                     // NOTE(write_volatile) see note above
-//                     unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, *byte) }
-//                     break 'l;
-//                 } else {
-//                     // try again
-//                 }
-//             }
-//         }
-
-//         // wait until the transmission of the last byte is done
-//         while self.spi.sr.read().bsy().bit_is_set() {}
-
-//         // clear OVR flag
-//         unsafe {
-//             ptr::read_volatile(&self.spi.dr as *const _ as *const u8);
-//         }
-//         self.spi.sr.read();
-
-//         Ok(())
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::prescaler;
+
+    #[test]
+    fn exact_dividers() {
+        for br in 0..8 {
+            assert_eq!(prescaler(84_000_000, 84_000_000 >> (br + 1)), (br, 84_000_000 >> (br + 1)));
+        }
+    }
+
+    #[test]
+    fn rounds_down_to_slower_clock() {
+        assert_eq!(prescaler(84_000_000, 20_000_000), (2, 10_500_000));
+        assert_eq!(prescaler(84_000_000, 10_499_999), (3, 5_250_000));
+    }
+
+    #[test]
+    fn ceiling_is_half_pclk() {
+        assert_eq!(prescaler(84_000_000, 84_000_000), (0, 42_000_000));
+        assert_eq!(prescaler(84_000_000, u32::max_value()), (0, 42_000_000));
+    }
+
+    #[test]
+    fn floor_is_pclk_over_256() {
+        assert_eq!(prescaler(84_000_000, 328_125), (7, 328_125));
+        assert_eq!(prescaler(84_000_000, 600_000), (7, 328_125));
+    }
+
+    #[test]
+    fn below_floor_saturates() {
+        assert_eq!(prescaler(84_000_000, 328_124), (7, 328_125));
+        assert_eq!(prescaler(84_000_000, 0), (7, 328_125));
+    }
+}